@@ -0,0 +1,148 @@
+// Packs the live agents into the front of another set of buffers, keeping
+// their order. `count_live` counts the live agents in each group of 256
+// slots, `sum_groups` turns the counts into where each group's first live
+// agent lands, and `scatter` moves every live agent to its place.
+
+struct Agent {
+    position: vec2<f32>,
+    angle: f32,
+    species_index: u32,
+};
+
+struct Agents {
+    agents: array<Agent>,
+};
+
+// The slots of the agent buffer bound to this dispatch. The dispatch is
+// folded into rows of `row_len` agents, and `first_index` is the population
+// index of the buffer's first agent.
+struct AgentRange {
+    first_index: u32,
+    num_agents: u32,
+    row_len: u32,
+    capacity: u32,
+};
+
+// Where the bound buffer of compacted agents falls in the population.
+struct Destination {
+    first_index: u32,
+    capacity: u32,
+};
+
+struct GroupCounts {
+    counts: array<u32>,
+};
+
+// `species_index` of an agent removed by the delete brush.
+const DEAD: u32 = 4294967295u;
+
+// Matches `agent_buffers::CHUNK_ALIGNMENT`, so groups never straddle buffers.
+const GROUP_SIZE: u32 = 256u;
+
+@group(0)
+@binding(0)
+var<storage, read> agents: Agents;
+
+@group(0)
+@binding(1)
+var<uniform> range: AgentRange;
+
+@group(0)
+@binding(2)
+var<storage, read_write> group_counts: GroupCounts;
+
+@group(0)
+@binding(3)
+var<storage, read_write> compacted: Agents;
+
+@group(0)
+@binding(4)
+var<uniform> destination: Destination;
+
+var<workgroup> ranks: array<u32, 256>;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+};
+
+fn is_live(index: u32) -> bool {
+    return index < range.num_agents && agents.agents[index].species_index != DEAD;
+}
+
+// Replaces each lane's flag with the number of live agents up to and
+// including it in the group.
+fn rank(local_index: u32, live: bool) {
+    ranks[local_index] = select(0u, 1u, live);
+
+    for (var offset = 1u; offset < GROUP_SIZE; offset = offset * 2u) {
+        workgroupBarrier();
+
+        var sum = ranks[local_index];
+        if (local_index >= offset) {
+            sum = sum + ranks[local_index - offset];
+        }
+
+        workgroupBarrier();
+        ranks[local_index] = sum;
+    }
+
+    workgroupBarrier();
+}
+
+@compute
+@workgroup_size(256)
+fn count_live(input: ComputeInput) {
+    let id = input.global_invocation_id;
+    let index = id.x + id.y * range.row_len;
+    let live = is_live(index);
+
+    rank(input.local_index, live);
+
+    // Groups past the slots in use are left alone; they may belong to the
+    // next buffer.
+    let group_start = index - input.local_index;
+
+    if (input.local_index == GROUP_SIZE - 1u && group_start < range.num_agents) {
+        group_counts.counts[(range.first_index + group_start) / GROUP_SIZE] = ranks[input.local_index];
+    }
+}
+
+// Run as a single invocation. Even at the agent limit this is a few hundred
+// thousand additions, and compaction is rare.
+@compute
+@workgroup_size(1)
+fn sum_groups() {
+    var total = 0u;
+
+    for (var group = 0u; group < arrayLength(&group_counts.counts); group = group + 1u) {
+        let count = group_counts.counts[group];
+        group_counts.counts[group] = total;
+        total = total + count;
+    }
+}
+
+@compute
+@workgroup_size(256)
+fn scatter(input: ComputeInput) {
+    let id = input.global_invocation_id;
+    let index = id.x + id.y * range.row_len;
+    let live = is_live(index);
+
+    rank(input.local_index, live);
+
+    if (!live) {
+        return;
+    }
+
+    let group = (range.first_index + index) / GROUP_SIZE;
+    let place = group_counts.counts[group] + ranks[input.local_index] - 1u;
+
+    // Agents landing in another destination buffer are moved by the
+    // dispatch bound to it. Below `first_index` the subtraction wraps.
+    let slot = place - destination.first_index;
+
+    if (slot < destination.capacity) {
+        compacted.agents[slot] = agents.agents[index];
+    }
+}
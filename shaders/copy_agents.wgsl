@@ -1,12 +1,26 @@
 struct Agent {
     position: vec2<f32>,
     angle: f32,
+    species_index: u32,
 };
 
 struct Agents {
     agents: array<Agent>,
 };
 
+// The slots of the agent buffer bound to this dispatch. The dispatch is
+// folded into rows of `row_len` agents, and `first_index` is the population
+// index of the buffer's first agent.
+struct AgentRange {
+    first_index: u32,
+    num_agents: u32,
+    row_len: u32,
+    capacity: u32,
+};
+
+// `species_index` of an agent removed by the delete brush.
+const DEAD: u32 = 4294967295u;
+
 @group(0)
 @binding(0)
 var<storage, read> agents: Agents;
@@ -15,6 +29,10 @@ var<storage, read> agents: Agents;
 @binding(1)
 var render_texture: texture_storage_2d<rgba16float, write>;
 
+@group(0)
+@binding(2)
+var<uniform> range: AgentRange;
+
 struct ComputeInput {
     @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
 };
@@ -23,17 +41,28 @@ struct ComputeInput {
 @workgroup_size(16)
 fn cs_main(input: ComputeInput) {
     let id = input.global_invocation_id;
-    let total_agents = arrayLength(&agents.agents);
+    let index = id.x + id.y * range.row_len;
 
-    if (id.x >= total_agents) {
+    if (index >= range.num_agents) {
         return;
     }
 
-    let agent = agents.agents[id.x];
+    let agent = agents.agents[index];
+
+    if (agent.species_index == DEAD) {
+        return;
+    }
 
     let x = i32(agent.position.x);
     let y = i32(agent.position.y);
     let coords = vec2<i32>(x, y);
 
-    textureStore(render_texture, coords, vec4<f32>(1.0, 1.0, 1.0, 1.0));
+    let species_mask = vec4<f32>(
+        f32(agent.species_index == 0u),
+        f32(agent.species_index == 1u),
+        f32(agent.species_index == 2u),
+        f32(agent.species_index == 3u),
+    );
+
+    textureStore(render_texture, coords, species_mask);
 }
@@ -0,0 +1,77 @@
+struct Globals {
+    width: u32,
+    height: u32,
+    trail_weight: f32,
+    // Non-zero when there are food sources to add from the food map.
+    fed: u32,
+};
+
+struct Time {
+    time: u32,
+    delta_time: f32,
+};
+
+// Agents landing on each texel this step, four species channels per texel.
+struct DepositCounts {
+    counts: array<atomic<u32>>,
+};
+
+@group(0)
+@binding(0)
+var<uniform> globals: Globals;
+
+@group(0)
+@binding(1)
+var<uniform> time: Time;
+
+@group(0)
+@binding(2)
+var trail_map: texture_storage_2d<rgba16float, read>;
+
+@group(0)
+@binding(3)
+var<storage, read_write> deposit_counts: DepositCounts;
+
+@group(0)
+@binding(4)
+var deposit_map: texture_storage_2d<rgba16float, write>;
+
+// Attractant added per second, per texel and trail channel.
+@group(0)
+@binding(5)
+var food_map: texture_2d<f32>;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+};
+
+@compute
+@workgroup_size(8, 8)
+fn cs_main(input: ComputeInput) {
+    let id = input.global_invocation_id;
+
+    if (id.x >= globals.width || id.y >= globals.height) {
+        return;
+    }
+
+    let texel = (id.y * globals.width + id.x) * 4u;
+
+    // Taking the counts also clears them for the next step.
+    let counts = vec4<f32>(
+        f32(atomicExchange(&deposit_counts.counts[texel], 0u)),
+        f32(atomicExchange(&deposit_counts.counts[texel + 1u], 0u)),
+        f32(atomicExchange(&deposit_counts.counts[texel + 2u], 0u)),
+        f32(atomicExchange(&deposit_counts.counts[texel + 3u], 0u)),
+    );
+
+    let coords = vec2<i32>(i32(id.x), i32(id.y));
+    let current_map = textureLoad(trail_map, coords);
+
+    var deposit = counts * globals.trail_weight * time.delta_time;
+
+    if (globals.fed != 0u) {
+        deposit = deposit + textureLoad(food_map, coords, 0) * time.delta_time;
+    }
+
+    textureStore(deposit_map, coords, min(vec4<f32>(1.0, 1.0, 1.0, 1.0), current_map + deposit));
+}
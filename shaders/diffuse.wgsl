@@ -3,13 +3,29 @@ struct Globals {
     height: u32,
     diffuse_rate: f32,
     decay_rate: f32,
+    boundary: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
 };
 
+// Matches `BoundaryMode::shader_index`.
+const BOUNDARY_TORUS: u32 = 1u;
+
 struct Time {
     time: u32,
     delta_time: f32,
 };
 
+// Attractant per second at `position`, falling off to nothing at `radius`.
+// Negative amounts erase; zero turns the brush off.
+struct Brush {
+    position: vec2<f32>,
+    radius: f32,
+    amount: f32,
+    channels: vec4<f32>,
+};
+
 @group(0)
 @binding(0)
 var<uniform> globals: Globals;
@@ -26,6 +42,14 @@ var trail_map: texture_storage_2d<rgba16float, read>;
 @binding(3)
 var diffuse_trail_map: texture_storage_2d<rgba16float, write>;
 
+@group(0)
+@binding(4)
+var obstacle_map: texture_2d<f32>;
+
+@group(0)
+@binding(5)
+var<uniform> brush: Brush;
+
 struct ComputeInput {
     @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
 };
@@ -41,23 +65,51 @@ fn cs_main(input: ComputeInput) {
 
     let coords = vec2<i32>(i32(id.x), i32(id.y));
 
+    // Nothing lingers on walls.
+    if (textureLoad(obstacle_map, coords, 0).r > 0.5) {
+        textureStore(diffuse_trail_map, coords, vec4<f32>(0.0, 0.0, 0.0, 0.0));
+        return;
+    }
+
+    // Only open neighbours are averaged, so trails do not bleed through
+    // walls.
+    var count: f32 = 0.0;
     var sum: vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
     let original_col = textureLoad(trail_map, coords);
     for (var offset_x: i32 = -1; offset_x <= 1; offset_x = offset_x + 1) {
         for (var offset_y: i32 = -1; offset_y <= 1; offset_y = offset_y + 1) {
-            let sample_x = min(globals.width - 1u, max(0u, id.x + u32(offset_x)));
-            let sample_y = min(globals.height - 1u, max(0u, id.y + u32(offset_y)));
+            var sample_x = min(globals.width - 1u, max(0u, id.x + u32(offset_x)));
+            var sample_y = min(globals.height - 1u, max(0u, id.y + u32(offset_y)));
+
+            if (globals.boundary == BOUNDARY_TORUS) {
+                sample_x = (id.x + globals.width + u32(offset_x)) % globals.width;
+                sample_y = (id.y + globals.height + u32(offset_y)) % globals.height;
+            }
 
             let offset_coords = vec2<i32>(i32(sample_x), i32(sample_y));
-            let texture_state = textureLoad(trail_map, offset_coords);
-            sum = sum + texture_state;
+
+            if (textureLoad(obstacle_map, offset_coords, 0).r <= 0.5) {
+                let texture_state = textureLoad(trail_map, offset_coords);
+                sum = sum + texture_state;
+                count = count + 1.0;
+            }
         }
     }
 
-    let blurred_col = sum / 9.0;
+    let blurred_col = sum / count;
     let diffuse_weight = clamp(globals.diffuse_rate * time.delta_time, 0.0, 1.0);
     let blurred_col2 = original_col * (1.0 - diffuse_weight) + blurred_col * diffuse_weight;
 
-    let output = max(vec4<f32>(0.0, 0.0, 0.0, 0.0), blurred_col2 - globals.decay_rate * time.delta_time);
+    var output = max(vec4<f32>(0.0, 0.0, 0.0, 0.0), blurred_col2 - globals.decay_rate * time.delta_time);
+
+    // The paint brush adds to or erases from the result.
+    if (brush.amount != 0.0) {
+        let offset = vec2<f32>(id.xy) + 0.5 - brush.position;
+        let falloff = max(0.0, 1.0 - sqrt(offset.x * offset.x + offset.y * offset.y) / brush.radius);
+        let painted = output + brush.channels * brush.amount * falloff * time.delta_time;
+
+        output = clamp(painted, vec4<f32>(0.0), vec4<f32>(1.0));
+    }
+
     textureStore(diffuse_trail_map, coords, output);
 }
@@ -8,13 +8,21 @@ struct VertexOutput {
     @location(0) tex_coord: vec2<f32>,
 };
 
+struct Transform {
+    scale: vec2<f32>,
+};
+
+@group(0)
+@binding(3)
+var<uniform> transform: Transform;
+
 @vertex
 fn vs_main(
     input: VertexInput,
 ) -> VertexOutput {
     var out: VertexOutput;
     
-    out.position = vec4<f32>(input.position.x, input.position.y, 0.0, 1.0);
+    out.position = vec4<f32>(input.position.xy * transform.scale, 0.0, 1.0);
     out.tex_coord = input.tex_coord;
 
     return out;
@@ -28,13 +36,31 @@ var sim_texture: texture_2d<f32>;
 @binding(1)
 var sim_sampler: sampler;
 
+struct SpeciesColors {
+    colors: array<vec4<f32>, 4>,
+};
+
+@group(0)
+@binding(2)
+var<uniform> species_colors: SpeciesColors;
+
+@group(0)
+@binding(4)
+var obstacle_map: texture_2d<f32>;
+
+const WALL_COLOR: vec3<f32> = vec3<f32>(0.25, 0.25, 0.3);
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
     let sample = textureSample(sim_texture, sim_sampler, input.tex_coord);
 
-    if (sample.x > 0.0 || sample.y > 0.0 || sample.z > 0.0) {
-        return vec4<f32>(sample.w, sample.w, sample.w, sample.w);
-    }
+    // Each trail channel belongs to one species, tinted with its color.
+    let color = sample.x * species_colors.colors[0]
+        + sample.y * species_colors.colors[1]
+        + sample.z * species_colors.colors[2]
+        + sample.w * species_colors.colors[3];
+
+    let wall = textureSample(obstacle_map, sim_sampler, input.tex_coord).r;
 
-    return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    return vec4<f32>(mix(min(color.rgb, vec3<f32>(1.0, 1.0, 1.0)), WALL_COLOR, wall), 1.0);
 }
@@ -0,0 +1,57 @@
+struct Globals {
+    screen_size: vec2<f32>,
+    _padding: vec2<f32>,
+};
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) tex_coord: vec2<f32>,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) tex_coord: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@group(0)
+@binding(0)
+var<uniform> globals: Globals;
+
+@group(1)
+@binding(0)
+var gui_texture: texture_2d<f32>;
+
+@group(1)
+@binding(1)
+var gui_sampler: sampler;
+
+// egui hands out gamma-space vertex colors, the surface expects linear ones.
+fn linear_from_srgb(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / vec3<f32>(12.92);
+    let higher = pow((srgb + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs_main(input: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    out.position = vec4<f32>(
+        2.0 * input.position.x / globals.screen_size.x - 1.0,
+        1.0 - 2.0 * input.position.y / globals.screen_size.y,
+        0.0,
+        1.0,
+    );
+    out.tex_coord = input.tex_coord;
+    out.color = vec4<f32>(linear_from_srgb(input.color.rgb), input.color.a);
+
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    return input.color * textureSample(gui_texture, gui_sampler, input.tex_coord);
+}
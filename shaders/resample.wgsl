@@ -0,0 +1,102 @@
+struct Globals {
+    old_width: u32,
+    old_height: u32,
+    new_width: u32,
+    new_height: u32,
+};
+
+struct Agent {
+    position: vec2<f32>,
+    angle: f32,
+    species_index: u32,
+};
+
+struct Agents {
+    agents: array<Agent>,
+};
+
+// The slots of the agent buffer bound to this dispatch. The dispatch is
+// folded into rows of `row_len` agents, and `first_index` is the population
+// index of the buffer's first agent.
+struct AgentRange {
+    first_index: u32,
+    num_agents: u32,
+    row_len: u32,
+    capacity: u32,
+};
+
+@group(0)
+@binding(0)
+var<uniform> globals: Globals;
+
+@group(0)
+@binding(1)
+var old_trail_map: texture_storage_2d<rgba16float, read>;
+
+@group(0)
+@binding(2)
+var new_trail_map: texture_storage_2d<rgba16float, write>;
+
+@group(0)
+@binding(3)
+var<storage, read_write> agents: Agents;
+
+@group(0)
+@binding(4)
+var<uniform> range: AgentRange;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+};
+
+fn load_old(coords: vec2<i32>) -> vec4<f32> {
+    let max_coords = vec2<i32>(i32(globals.old_width) - 1, i32(globals.old_height) - 1);
+
+    return textureLoad(old_trail_map, clamp(coords, vec2<i32>(0, 0), max_coords));
+}
+
+fn scale() -> vec2<f32> {
+    return vec2<f32>(f32(globals.new_width), f32(globals.new_height))
+        / vec2<f32>(f32(globals.old_width), f32(globals.old_height));
+}
+
+// Bilinearly samples the old trail map at the center of each new texel.
+@compute
+@workgroup_size(8, 8)
+fn resample_trail(input: ComputeInput) {
+    let id = input.global_invocation_id;
+
+    if (id.x >= globals.new_width || id.y >= globals.new_height) {
+        return;
+    }
+
+    let source = (vec2<f32>(f32(id.x), f32(id.y)) + 0.5) / scale() - 0.5;
+    let base = floor(source);
+    let t = source - base;
+    let coords = vec2<i32>(base);
+
+    let top = mix(load_old(coords), load_old(coords + vec2<i32>(1, 0)), t.x);
+    let bottom = mix(
+        load_old(coords + vec2<i32>(0, 1)),
+        load_old(coords + vec2<i32>(1, 1)),
+        t.x,
+    );
+
+    textureStore(new_trail_map, vec2<i32>(i32(id.x), i32(id.y)), mix(top, bottom, t.y));
+}
+
+@compute
+@workgroup_size(64)
+fn rescale_agents(input: ComputeInput) {
+    let id = input.global_invocation_id;
+    let index = id.x + id.y * range.row_len;
+
+    if (index >= range.num_agents) {
+        return;
+    }
+
+    let max_position = vec2<f32>(f32(globals.new_width) - 1.0, f32(globals.new_height) - 1.0);
+    let position = agents.agents[index].position * scale();
+
+    agents.agents[index].position = clamp(position, vec2<f32>(0.0, 0.0), max_position);
+}
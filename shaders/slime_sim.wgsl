@@ -1,7 +1,32 @@
 struct Globals {
-    trail_weight: f32,
     width: u32,
     height: u32,
+    seed: u32,
+    boundary: u32,
+};
+
+// Matches `BoundaryMode::shader_index`.
+const BOUNDARY_TORUS: u32 = 1u;
+const BOUNDARY_REFLECT: u32 = 2u;
+const BOUNDARY_RESPAWN_RANDOM: u32 = 3u;
+const BOUNDARY_RESPAWN_CENTER: u32 = 4u;
+
+const PI: f32 = 3.14159265;
+
+// `species_index` of an agent removed by the delete brush.
+const DEAD: u32 = 4294967295u;
+
+// Added to a sensor's weight for every wall texel it covers.
+const OBSTACLE_WEIGHT: f32 = -10.0;
+
+// A brush under the cursor, off when `amount` is zero. The attractor adds
+// `amount` to a sensor at `position`, falling off to nothing at `radius`, and
+// repels when negative. The eraser deletes agents within `radius`.
+struct Brush {
+    position: vec2<f32>,
+    radius: f32,
+    amount: f32,
+    channels: vec4<f32>,
 };
 
 struct TimeBuffer {
@@ -15,17 +40,40 @@ struct SpeciesSetting {
     sensor_angle_degrees: f32,
     sensor_offset_dst: f32,
     sensor_size: i32,
+    _padding0: i32,
+    _padding1: i32,
+    _padding2: i32,
+};
+
+struct SpeciesSettings {
+    species: array<SpeciesSetting, 4>,
 };
 
 struct Agent {
     position: vec2<f32>,
     angle: f32,
+    species_index: u32,
 };
 
 struct Agents {
     agents: array<Agent>,
 };
 
+// The slots of the agent buffer bound to this dispatch. The dispatch is
+// folded into rows of `row_len` agents, and `first_index` is the population
+// index of the buffer's first agent.
+struct AgentRange {
+    first_index: u32,
+    num_agents: u32,
+    row_len: u32,
+    capacity: u32,
+};
+
+// Agents landing on each texel this step, four species channels per texel.
+struct DepositCounts {
+    counts: array<atomic<u32>>,
+};
+
 @group(0)
 @binding(0)
 var<uniform> globals: Globals;
@@ -36,7 +84,7 @@ var<uniform> time: TimeBuffer;
 
 @group(0)
 @binding(2)
-var<uniform> species_settings: SpeciesSetting;
+var<uniform> species_settings: SpeciesSettings;
 
 @group(0)
 @binding(3)
@@ -48,7 +96,28 @@ var trail_map_read: texture_storage_2d<rgba16float, read>;
 
 @group(0)
 @binding(5)
-var trail_map_write: texture_storage_2d<rgba16float, write>;
+var<storage, read_write> deposit_counts: DepositCounts;
+
+@group(0)
+@binding(6)
+var obstacle_map: texture_2d<f32>;
+
+@group(0)
+@binding(7)
+var<uniform> attractor: Brush;
+
+@group(0)
+@binding(8)
+var<uniform> eraser: Brush;
+
+@group(0)
+@binding(9)
+var<uniform> range: AgentRange;
+
+// Agents the eraser has deleted since the buffer was last compacted.
+@group(0)
+@binding(10)
+var<storage, read_write> deleted: atomic<u32>;
 
 
 struct ComputeInput {
@@ -70,28 +139,75 @@ fn hash(state: u32) -> u32 {
     return state6;
 }
 
-fn sense(agent: Agent, sensor_angle_offset: f32) -> f32 {
+// One-hot mask selecting the trail channel owned by a species.
+fn species_mask(species_index: u32) -> vec4<f32> {
+    return vec4<f32>(
+        f32(species_index == 0u),
+        f32(species_index == 1u),
+        f32(species_index == 2u),
+        f32(species_index == 3u),
+    );
+}
+
+// Wraps a texel coordinate, which may be negative, into [0, size).
+fn wrap(value: i32, size: u32) -> u32 {
+    let signed_size = i32(size);
+    return u32((value % signed_size + signed_size) % signed_size);
+}
+
+// Wraps a position onto the torus.
+fn wrap_position(position: vec2<f32>, size: vec2<f32>) -> vec2<f32> {
+    let wrapped = position - floor(position / size) * size;
+
+    // A tiny negative position can round up to the far edge.
+    return select(wrapped, vec2<f32>(0.0, 0.0), wrapped >= size);
+}
+
+fn is_obstacle(position: vec2<f32>) -> bool {
+    return textureLoad(obstacle_map, vec2<i32>(position), 0).r > 0.5;
+}
+
+fn sense(agent: Agent, species: SpeciesSetting, sensor_angle_offset: f32) -> f32 {
     let sensor_angle = agent.angle + sensor_angle_offset;
     let sensor_dir = vec2<f32>(cos(sensor_angle), sin(sensor_angle));
 
-    let sensor_pos = agent.position + sensor_dir * species_settings.sensor_offset_dst;
+    let sensor_pos = agent.position + sensor_dir * species.sensor_offset_dst;
 
     let sensor_center_x = u32(sensor_pos.x);
     let sensor_center_y = u32(sensor_pos.y);
 
+    // Attracted to the agent's own species channel, repelled by the others.
+    let mask = species_mask(agent.species_index) * 2.0 - 1.0;
+
     var sum: f32 = 0.0;
 
-    for(var offset_x: i32 = -species_settings.sensor_size; offset_x <= species_settings.sensor_size; offset_x = offset_x + 1) {
-        for(var offset_y: i32 = -species_settings.sensor_size; offset_y <= species_settings.sensor_size; offset_y = offset_y + 1) {
-            let sample_x = min(globals.width - 1u, max(0u, sensor_center_x + u32(offset_x)));
-            let sample_y = min(globals.height - 1u, max(0u, sensor_center_y + u32(offset_y)));
+    for(var offset_x: i32 = -species.sensor_size; offset_x <= species.sensor_size; offset_x = offset_x + 1) {
+        for(var offset_y: i32 = -species.sensor_size; offset_y <= species.sensor_size; offset_y = offset_y + 1) {
+            var sample_x = min(globals.width - 1u, max(0u, sensor_center_x + u32(offset_x)));
+            var sample_y = min(globals.height - 1u, max(0u, sensor_center_y + u32(offset_y)));
 
-            let current_map = textureLoad(trail_map_read, vec2<i32>(i32(sample_x), i32(sample_y)));
-            let mask = vec4<f32>(1.0, 1.0, 1.0, 1.0) * 2.0 - 1.0;
-            sum = sum + dot(mask, current_map);
+            if (globals.boundary == BOUNDARY_TORUS) {
+                sample_x = wrap(i32(floor(sensor_pos.x)) + offset_x, globals.width);
+                sample_y = wrap(i32(floor(sensor_pos.y)) + offset_y, globals.height);
+            }
+
+            let coords = vec2<i32>(i32(sample_x), i32(sample_y));
+
+            if (textureLoad(obstacle_map, coords, 0).r > 0.5) {
+                sum = sum + OBSTACLE_WEIGHT;
+            } else {
+                let current_map = textureLoad(trail_map_read, coords);
+                sum = sum + dot(mask, current_map);
+            }
         }
     }
 
+    if (attractor.amount != 0.0) {
+        let offset = sensor_pos - attractor.position;
+        let falloff = max(0.0, 1.0 - sqrt(offset.x * offset.x + offset.y * offset.y) / attractor.radius);
+        sum = sum + attractor.amount * falloff;
+    }
+
     return sum;
 }
 
@@ -100,28 +216,38 @@ fn sense(agent: Agent, sensor_angle_offset: f32) -> f32 {
 fn cs_main(input: ComputeInput) {
     let id = input.global_invocation_id;
 
-    let total_agents = arrayLength(&agents.agents);
-    let index = id.x;
+    let index = id.x + id.y * range.row_len;
 
-    if (index >= total_agents) {
+    if (index >= range.num_agents) {
         return;
     }
 
     var agent: Agent = agents.agents[index];
-    
+
+    if (agent.species_index == DEAD) {
+        return;
+    }
+
+    if (eraser.amount != 0.0 && distance(agent.position, eraser.position) <= eraser.radius) {
+        agents.agents[index].species_index = DEAD;
+        atomicAdd(&deleted, 1u);
+        return;
+    }
+    let species = species_settings.species[agent.species_index];
+
     let random = hash(
         u32(agent.position.y) * globals.width
             + u32(agent.position.x)
-            + hash(id.x + time.time * 100000u)
+            + hash(range.first_index + index + time.time * 100000u + hash(globals.seed))
     );
 
-    let sensor_angle_rad = species_settings.sensor_angle_degrees * (3.1415 / 180.0);
-    let weight_forward = sense(agent, 0.0);
-    let weight_left = sense(agent, sensor_angle_rad);
-    let weight_right = sense(agent, -sensor_angle_rad);
+    let sensor_angle_rad = species.sensor_angle_degrees * (3.1415 / 180.0);
+    let weight_forward = sense(agent, species, 0.0);
+    let weight_left = sense(agent, species, sensor_angle_rad);
+    let weight_right = sense(agent, species, -sensor_angle_rad);
 
     let random_steer_strength = scale_to_range(f32(random));
-    let turn_speed = species_settings.turn_speed * 2.0 * 3.1415;
+    let turn_speed = species.turn_speed * 2.0 * 3.1415;
 
     if (weight_forward > weight_left && weight_forward > weight_right) {
         agents.agents[index].angle = agent.angle + 0.0;
@@ -134,23 +260,71 @@ fn cs_main(input: ComputeInput) {
     }
 
     let direction = vec2<f32>(cos(agent.angle), sin(agent.angle));
-    var new_pos: vec2<f32> = agent.position + direction * time.delta_time * species_settings.move_speed;
+    var new_pos: vec2<f32> = agent.position + direction * time.delta_time * species.move_speed;
 
     let global_width = f32(globals.width);
     let global_height = f32(globals.height);
+    let size = vec2<f32>(global_width, global_height);
+
+    // Agents that stay on the map deposit, as do those wrapped or reflected
+    // back onto it.
+    var deposit = true;
+
+    let new_rand = hash(random);
+    let random_angle = scale_to_range(f32(new_rand)) * 2.0 * 3.1415;
 
     if (new_pos.x < 0.0 || new_pos.x >= global_width || new_pos.y < 0.0 || new_pos.y >= global_height) {
-        let new_rand = hash(random);
-        let random_angle = scale_to_range(f32(new_rand)) * 2.0 * 3.1415;
-
-        new_pos.x = min(global_width - 1.0, max(0.0, new_pos.x));
-        new_pos.y = min(global_height - 1.0, max(0.0, new_pos.y));
-        agents.agents[index].angle = random_angle;
-    } else {
-        let current_pos = vec2<i32>(i32(new_pos.x), i32(new_pos.y));
-        let current_map = textureLoad(trail_map_read, current_pos);
-        
-        textureStore(trail_map_write, current_pos, min(vec4<f32>(1.0, 1.0, 1.0, 1.0), current_map + vec4<f32>(1.0, 1.0, 1.0, 0.6) * globals.trail_weight * time.delta_time));
+        if (globals.boundary == BOUNDARY_TORUS) {
+            new_pos = wrap_position(new_pos, size);
+        } else if (globals.boundary == BOUNDARY_REFLECT) {
+            var angle = agents.agents[index].angle;
+
+            if (new_pos.x < 0.0 || new_pos.x >= global_width) {
+                new_pos.x = select(-new_pos.x, 2.0 * global_width - new_pos.x, new_pos.x >= global_width);
+                angle = PI - angle;
+            }
+            if (new_pos.y < 0.0 || new_pos.y >= global_height) {
+                new_pos.y = select(-new_pos.y, 2.0 * global_height - new_pos.y, new_pos.y >= global_height);
+                angle = -angle;
+            }
+
+            new_pos = clamp(new_pos, vec2<f32>(0.0, 0.0), size - 1.0);
+            agents.agents[index].angle = angle;
+        } else if (globals.boundary == BOUNDARY_RESPAWN_RANDOM) {
+            let x_rand = hash(new_rand);
+            let y_rand = hash(x_rand);
+
+            new_pos = vec2<f32>(scale_to_range(f32(x_rand)), scale_to_range(f32(y_rand))) * (size - 1.0);
+            agents.agents[index].angle = random_angle;
+            deposit = false;
+        } else if (globals.boundary == BOUNDARY_RESPAWN_CENTER) {
+            new_pos = size * 0.5;
+            agents.agents[index].angle = random_angle;
+            deposit = false;
+        } else {
+            new_pos.x = min(global_width - 1.0, max(0.0, new_pos.x));
+            new_pos.y = min(global_height - 1.0, max(0.0, new_pos.y));
+            agents.agents[index].angle = random_angle;
+            deposit = false;
+        }
+    }
+
+    // Walls stop agents coming from open space and turn them, like the
+    // clamped edge. Agents that start inside a wall can walk out of it.
+    if (is_obstacle(new_pos)) {
+        if (!is_obstacle(agent.position)) {
+            new_pos = agent.position;
+            agents.agents[index].angle = random_angle;
+        }
+
+        deposit = false;
+    }
+
+    if (deposit) {
+        // Counted rather than written, so every agent sharing a texel adds
+        // to it regardless of scheduling.
+        let texel = u32(new_pos.y) * globals.width + u32(new_pos.x);
+        atomicAdd(&deposit_counts.counts[texel * 4u + agent.species_index], 1u);
     }
 
     agents.agents[index].position = new_pos;
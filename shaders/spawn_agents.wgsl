@@ -0,0 +1,129 @@
+// Places agents by the spawn pattern straight into the agent buffer. Every
+// agent is hashed from the seed and its index alone, so `cpu.rs` can place
+// the same ones.
+struct Spawn {
+    seed: u32,
+    // Population indices of the agents to place.
+    first_index: u32,
+    num_agents: u32,
+    num_species: u32,
+    // Matches `SpawnBuffer::new`.
+    mode: u32,
+    inward: u32,
+    width: u32,
+    height: u32,
+    // The radius in x for the circle and ring, the width and height of the
+    // rect.
+    extent: vec2<f32>,
+    _padding: vec2<f32>,
+};
+
+const MODE_POINT: u32 = 0u;
+const MODE_RANDOM: u32 = 1u;
+const MODE_CIRCLE: u32 = 2u;
+const MODE_RING: u32 = 3u;
+const MODE_RECT: u32 = 4u;
+
+const PI: f32 = 3.14159265;
+
+struct Agent {
+    position: vec2<f32>,
+    angle: f32,
+    species_index: u32,
+};
+
+struct Agents {
+    agents: array<Agent>,
+};
+
+// The slots of the agent buffer bound to this dispatch. The dispatch is
+// folded into rows of `row_len` agents, and `first_index` is the population
+// index of the buffer's first agent.
+struct AgentRange {
+    first_index: u32,
+    num_agents: u32,
+    row_len: u32,
+    capacity: u32,
+};
+
+@group(0)
+@binding(0)
+var<uniform> spawn: Spawn;
+
+@group(0)
+@binding(1)
+var<storage, read_write> agents: Agents;
+
+@group(0)
+@binding(2)
+var<uniform> range: AgentRange;
+
+struct ComputeInput {
+    @builtin(global_invocation_id) global_invocation_id: vec3<u32>,
+};
+
+fn hash(state: u32) -> u32 {
+    let state1 = state ^ 2747636419u;
+    let state2 = state1 * 2654435769u;
+    let state3 = state2 ^ state >> 16u;
+    let state4 = state3 * 2654435769u;
+    let state5 = state4 ^ state >> 16u;
+
+    return state5 * 2654435769u;
+}
+
+fn scale_to_range(state: f32) -> f32 {
+    return state / 4294967295.0;
+}
+
+@compute
+@workgroup_size(64)
+fn cs_main(input: ComputeInput) {
+    let id = input.global_invocation_id;
+    let slot = id.x + id.y * range.row_len;
+    let index = range.first_index + slot;
+
+    if (slot >= range.num_agents || index < spawn.first_index) {
+        return;
+    }
+
+    // Three draws in [0, 1], one after another from the agent's own state.
+    let state0 = hash(index ^ hash(spawn.seed));
+    let state1 = hash(state0);
+    let state2 = hash(state1);
+    let draws = vec3<f32>(
+        scale_to_range(f32(state0)),
+        scale_to_range(f32(state1)),
+        scale_to_range(f32(state2)),
+    );
+
+    let size = vec2<f32>(f32(spawn.width), f32(spawn.height));
+    let center = size * 0.5;
+
+    var position = center;
+    var angle = draws.z * 2.0 * PI;
+
+    if (spawn.mode == MODE_RANDOM) {
+        position = draws.xy * size;
+    } else if (spawn.mode == MODE_CIRCLE) {
+        // sqrt keeps the density uniform over the disc.
+        let distance = spawn.extent.x * sqrt(draws.x);
+        let theta = draws.y * 2.0 * PI;
+
+        position = center + distance * vec2<f32>(cos(theta), sin(theta));
+        angle = theta + PI;
+    } else if (spawn.mode == MODE_RING) {
+        let theta = draws.x * 2.0 * PI;
+
+        position = center + spawn.extent.x * vec2<f32>(cos(theta), sin(theta));
+        angle = select(theta, theta + PI, spawn.inward != 0u);
+    } else if (spawn.mode == MODE_RECT) {
+        position = center + (draws.xy - 0.5) * spawn.extent;
+    }
+
+    agents.agents[slot] = Agent(
+        clamp(position, vec2<f32>(0.0, 0.0), size - 1.0),
+        angle,
+        index % spawn.num_species,
+    );
+}
@@ -0,0 +1,290 @@
+//! The agents on the GPU. A single storage binding is capped by the device,
+//! so the population is split over as many buffers as that cap needs, each
+//! bound and dispatched on its own. Agent indices run on from one buffer to
+//! the next.
+
+use std::ops::Range;
+
+use crate::{
+    capture,
+    shader_pipeline::{self, Agent},
+};
+
+/// Buffers split at a multiple of this many agents, so the groups the
+/// compaction pass counts never straddle two of them.
+pub const CHUNK_ALIGNMENT: usize = 256;
+
+#[derive(Debug)]
+pub struct AgentBuffers {
+    buffers: Vec<wgpu::Buffer>,
+    /// Agents each buffer holds. The last one may hold fewer.
+    chunk_len: usize,
+}
+
+/// One of the buffers and where its agents fall in the population.
+pub struct Chunk<'a> {
+    pub buffer: &'a wgpu::Buffer,
+    pub first_index: usize,
+    pub len: usize,
+}
+
+impl AgentBuffers {
+    /// Room for `capacity` agents, with `agents` in the first slots.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capacity: usize,
+        agents: &[Agent],
+    ) -> Self {
+        let limits = device.limits();
+        let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let chunk_len = chunk_len(max_bytes as usize, capacity);
+
+        let buffers = split(capacity, chunk_len)
+            .map(|span| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("slime::shader::simulation::agents_buffer"),
+                    size: (span.len * std::mem::size_of::<Agent>()) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::STORAGE
+                        | wgpu::BufferUsages::COPY_SRC
+                        | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if buffers.len() > 1 {
+            log::info!(
+                "Splitting {} agent slots over {} buffers",
+                capacity,
+                buffers.len()
+            );
+        }
+
+        let agent_buffers = Self { buffers, chunk_len };
+        agent_buffers.write(queue, 0, agents);
+
+        agent_buffers
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = Chunk<'_>> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| Chunk {
+                buffer,
+                first_index: index * self.chunk_len,
+                len: buffer.size() as usize / std::mem::size_of::<Agent>(),
+            })
+    }
+
+    /// Where each buffer's slots fall in the population.
+    fn spans(&self) -> Vec<Span> {
+        self.chunks()
+            .map(|chunk| Span {
+                first_index: chunk.first_index,
+                len: chunk.len,
+            })
+            .collect()
+    }
+
+    /// Writes `agents` into the slots from `first_index` on, across buffers
+    /// where they straddle one.
+    pub fn write(&self, queue: &wgpu::Queue, first_index: usize, agents: &[Agent]) {
+        let source = Span {
+            first_index,
+            len: agents.len(),
+        };
+        let range = first_index..first_index + agents.len();
+
+        for transfer in transfers(&[source], &self.spans(), range) {
+            queue.write_buffer(
+                &self.buffers[transfer.to],
+                (transfer.to_offset * std::mem::size_of::<Agent>()) as wgpu::BufferAddress,
+                bytemuck::cast_slice(
+                    &agents[transfer.from_offset..transfer.from_offset + transfer.len],
+                ),
+            );
+        }
+    }
+
+    /// Copies the agents in the first `count` slots of `source` into the
+    /// same slots here, which may be split differently.
+    pub fn copy_from(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &AgentBuffers,
+        count: usize,
+    ) {
+        let agent_size = std::mem::size_of::<Agent>();
+
+        for transfer in transfers(&source.spans(), &self.spans(), 0..count) {
+            encoder.copy_buffer_to_buffer(
+                &source.buffers[transfer.from],
+                (transfer.from_offset * agent_size) as wgpu::BufferAddress,
+                &self.buffers[transfer.to],
+                (transfer.to_offset * agent_size) as wgpu::BufferAddress,
+                (transfer.len * agent_size) as wgpu::BufferAddress,
+            );
+        }
+    }
+
+    /// Reads back the agents in the first `count` slots.
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue, count: usize) -> Vec<Agent> {
+        let mut agents = Vec::with_capacity(count);
+
+        for chunk in self.chunks().take_while(|chunk| chunk.first_index < count) {
+            agents.extend(shader_pipeline::read_unaligned::<Agent>(
+                &capture::read_buffer(device, queue, chunk.buffer),
+            ));
+        }
+
+        agents.truncate(count);
+        agents
+    }
+}
+
+/// Where a buffer's slots fall in the population.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Span {
+    first_index: usize,
+    len: usize,
+}
+
+/// Agents a buffer of at most `max_bytes` holds, for a population of
+/// `capacity`.
+fn chunk_len(max_bytes: usize, capacity: usize) -> usize {
+    let fits = max_bytes / std::mem::size_of::<Agent>();
+
+    (fits / CHUNK_ALIGNMENT * CHUNK_ALIGNMENT).min(capacity)
+}
+
+/// Splits `capacity` slots into buffers of `chunk_len`, the last one taking
+/// what is left.
+fn split(capacity: usize, chunk_len: usize) -> impl Iterator<Item = Span> {
+    (0..capacity)
+        .step_by(chunk_len)
+        .map(move |first_index| Span {
+            first_index,
+            len: chunk_len.min(capacity - first_index),
+        })
+}
+
+/// `len` slots moved from slot `from_offset` of buffer `from` to slot
+/// `to_offset` of buffer `to`.
+#[derive(Debug, PartialEq, Eq)]
+struct Transfer {
+    from: usize,
+    from_offset: usize,
+    to: usize,
+    to_offset: usize,
+    len: usize,
+}
+
+/// The transfers that move the slots in `range` from buffers split as `from`
+/// into the same slots of buffers split as `to`.
+fn transfers(from: &[Span], to: &[Span], range: Range<usize>) -> Vec<Transfer> {
+    let mut transfers = Vec::new();
+
+    for (to_index, to) in to.iter().enumerate() {
+        for (from_index, from) in from.iter().enumerate() {
+            let start = range.start.max(to.first_index).max(from.first_index);
+            let stop = range
+                .end
+                .min(to.first_index + to.len)
+                .min(from.first_index + from.len);
+
+            if start >= stop {
+                continue;
+            }
+
+            transfers.push(Transfer {
+                from: from_index,
+                from_offset: start - from.first_index,
+                to: to_index,
+                to_offset: start - to.first_index,
+                len: stop - start,
+            });
+        }
+    }
+
+    transfers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(first_index: usize, len: usize) -> Span {
+        Span { first_index, len }
+    }
+
+    fn transfer(from: [usize; 2], to: [usize; 2], len: usize) -> Transfer {
+        Transfer {
+            from: from[0],
+            from_offset: from[1],
+            to: to[0],
+            to_offset: to[1],
+            len,
+        }
+    }
+
+    #[test]
+    fn chunks_are_aligned_and_capped_by_capacity() {
+        let agent_size = std::mem::size_of::<Agent>();
+
+        assert_eq!(chunk_len(1000 * agent_size, 10_000), 768);
+        assert_eq!(chunk_len(1000 * agent_size, 500), 500);
+        assert_eq!(chunk_len(1 << 30, 1_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn last_chunk_takes_what_is_left() {
+        assert_eq!(
+            split(10, 4).collect::<Vec<_>>(),
+            [span(0, 4), span(4, 4), span(8, 2)]
+        );
+        assert_eq!(split(8, 4).collect::<Vec<_>>(), [span(0, 4), span(4, 4)]);
+        assert_eq!(split(3, 3).collect::<Vec<_>>(), [span(0, 3)]);
+    }
+
+    #[test]
+    fn writes_straddle_chunks() {
+        let chunks = split(10, 4).collect::<Vec<_>>();
+
+        // Six agents from slot 3 on, as `write` passes them.
+        assert_eq!(
+            transfers(&[span(3, 6)], &chunks, 3..9),
+            [
+                transfer([0, 0], [0, 3], 1),
+                transfer([0, 1], [1, 0], 4),
+                transfer([0, 5], [2, 0], 1),
+            ]
+        );
+
+        // Into the partly full last chunk only.
+        assert_eq!(
+            transfers(&[span(8, 2)], &chunks, 8..10),
+            [transfer([0, 0], [2, 0], 2)]
+        );
+    }
+
+    #[test]
+    fn copies_between_different_splits() {
+        let from = split(10, 4).collect::<Vec<_>>();
+        let to = split(12, 6).collect::<Vec<_>>();
+
+        assert_eq!(
+            transfers(&from, &to, 0..9),
+            [
+                transfer([0, 0], [0, 0], 4),
+                transfer([1, 0], [0, 4], 2),
+                transfer([1, 2], [1, 0], 2),
+                transfer([2, 0], [1, 2], 1),
+            ]
+        );
+
+        // Nothing past the slots asked for.
+        assert_eq!(transfers(&from, &to, 0..0), []);
+    }
+}
@@ -0,0 +1,199 @@
+//! Bookkeeping for the agent storage. Agents fill the first `count` slots of
+//! a buffer with a little spare capacity, so the spawn brush can add agents
+//! without a reallocation at first. The delete brush marks agents dead in
+//! place; their slots are only reclaimed by compacting the survivors when a
+//! spawn runs out of room, and the buffer grows when that is not enough.
+//!
+//! Both backends keep the same books, so agent indices, which seed the
+//! steering noise, stay in step. The GPU learns how many agents were deleted
+//! a frame or so late, and holds back a spawn that needs their slots until
+//! it knows.
+
+use crate::{
+    cpu,
+    pipeline::SpawnBuffer,
+    settings::{AppSettings, MAX_AGENTS},
+    shader_pipeline::Agent,
+};
+
+/// `species_index` of a deleted agent, skipped by every pass.
+pub const DEAD: u32 = u32::MAX;
+
+/// Spare slots allocated on top of the agents a run starts with.
+const SPARE: usize = 4_096;
+
+pub fn is_alive(agent: &Agent) -> bool {
+    agent.species_index != DEAD
+}
+
+/// Drops dead agents, keeping the survivors in order.
+pub fn compact(agents: &mut Vec<Agent>) {
+    agents.retain(is_alive);
+}
+
+/// Compacts `agents` and grows or shrinks them to `count`. Agents past
+/// `count` are dropped from the end, and new ones are placed by the spawn
+/// pattern as `SpawnPipeline` places them at `step`.
+pub fn resize(agents: &mut Vec<Agent>, count: usize, settings: &AppSettings, seed: u64, step: u32) {
+    compact(agents);
+
+    let first_index = agents.len();
+
+    if count <= first_index {
+        agents.truncate(count);
+    } else {
+        agents.extend(cpu::spawned_agents(&SpawnBuffer::new(
+            settings,
+            seed,
+            step,
+            first_index,
+            count - first_index,
+        )));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Slots {
+    /// Slots in use, dead agents included.
+    pub count: usize,
+    pub capacity: usize,
+    /// Dead agents among the slots in use, as far as they are counted.
+    deleted: usize,
+    /// Agents were deleted since the last compaction.
+    culled: bool,
+    /// A spawn ran out of room, so the next one does not warn again.
+    full: bool,
+}
+
+impl Slots {
+    pub fn new(count: usize) -> Self {
+        Self {
+            count,
+            capacity: (count + SPARE).min(MAX_AGENTS),
+            deleted: 0,
+            culled: false,
+            full: false,
+        }
+    }
+
+    /// Slots for a run resumed with `agents`, which a snapshot may save
+    /// with dead ones among them.
+    pub fn for_agents(agents: &[Agent]) -> Self {
+        let mut slots = Self::new(agents.len());
+        slots.deleted = agents.iter().filter(|agent| !is_alive(agent)).count();
+        slots.culled = slots.deleted > 0;
+
+        slots
+    }
+
+    /// Notes that the delete brush ran and may have left dead agents.
+    pub fn cull(&mut self) {
+        self.culled = true;
+    }
+
+    /// Records that `deleted` of the slots in use hold dead agents.
+    pub fn counted(&mut self, deleted: usize) {
+        self.deleted = deleted;
+    }
+
+    /// Agents alive in the slots in use.
+    pub fn live(&self) -> usize {
+        self.count - self.deleted
+    }
+
+    /// Whether the delete brush ran since the last compaction, so dead
+    /// agents may be left.
+    pub fn is_culled(&self) -> bool {
+        self.culled
+    }
+
+    /// Whether dead agents should be compacted away before adding
+    /// `additional` agents.
+    pub fn needs_compaction(&self, additional: usize) -> bool {
+        self.culled && self.count + additional > self.capacity
+    }
+
+    /// Records a compaction down to `count` live agents.
+    pub fn compacted(&mut self, count: usize) {
+        self.count = count;
+        self.deleted = 0;
+        self.culled = false;
+        self.full = false;
+    }
+
+    /// The capacity to grow to so `additional` more agents fit, or `None`
+    /// when they fit already or the storage is at the agent limit. It grows
+    /// by half again at least, so growing stays rare.
+    pub fn growth(&self, additional: usize) -> Option<usize> {
+        let needed = (self.count + additional).min(MAX_AGENTS);
+
+        (needed > self.capacity).then(|| {
+            needed
+                .max(self.capacity + self.capacity / 2)
+                .min(MAX_AGENTS)
+        })
+    }
+
+    /// Records that the storage grew to `capacity`.
+    pub fn grown(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// Takes up to `additional` free slots, returning how many were taken.
+    pub fn reserve(&mut self, additional: usize) -> usize {
+        let taken = additional.min(self.capacity - self.count);
+
+        if taken < additional && !self.full {
+            log::warn!(
+                "Agent storage is full at {} agents, not spawning more",
+                self.capacity
+            );
+        }
+
+        self.full = taken < additional;
+        self.count += taken;
+
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_by_half_again_at_least() {
+        let slots = Slots::new(10_000);
+        assert_eq!(slots.capacity, 10_000 + SPARE);
+
+        assert_eq!(slots.growth(SPARE), None);
+        assert_eq!(slots.growth(SPARE + 1), Some((10_000 + SPARE) * 3 / 2));
+        assert_eq!(slots.growth(50_000), Some(60_000));
+    }
+
+    #[test]
+    fn stops_growing_at_the_agent_limit() {
+        let mut slots = Slots::new(MAX_AGENTS - 1_000);
+        assert_eq!(slots.capacity, MAX_AGENTS);
+        assert_eq!(slots.growth(2_000), None);
+        assert_eq!(slots.reserve(2_000), 1_000);
+
+        let slots = Slots::new(MAX_AGENTS - 10 * SPARE);
+        assert_eq!(slots.growth(20 * SPARE), Some(MAX_AGENTS));
+    }
+
+    #[test]
+    fn compacts_before_growing() {
+        let mut slots = Slots::new(10_000);
+        assert!(!slots.needs_compaction(SPARE + 1));
+
+        slots.cull();
+        assert!(!slots.needs_compaction(SPARE));
+        assert!(slots.needs_compaction(SPARE + 1));
+
+        slots.counted(2_000);
+        slots.compacted(slots.live());
+        assert_eq!(slots.live(), 8_000);
+        assert_eq!(slots.growth(SPARE + 1), None);
+    }
+}
@@ -1,21 +1,7 @@
 use wgpu::{Features, Limits};
 use winit::{dpi::PhysicalSize, event_loop::EventLoop, window::WindowBuilder};
 
-pub struct AppSettings {
-    pub width: u32,
-    pub height: u32,
-    pub trail_weight: f32,
-    pub num_agents: usize,
-    pub steps_per_frame: usize,
-    pub move_speed: f32,
-    pub turn_speed: f32,
-    pub sensor_angle_degrees: f32,
-    pub sensor_offset_dst: f32,
-    pub sensor_size: i32,
-    pub decay_rate: f32,
-    pub diffuse_rate: f32,
-    pub agents_only: bool,
-}
+use crate::settings::AppSettings;
 
 pub struct App {
     pub settings: AppSettings,
@@ -35,6 +21,7 @@ impl App {
         let event_loop = EventLoop::new();
         let window = WindowBuilder::new()
             .with_title("Slime")
+            .with_inner_size(PhysicalSize::new(settings.width, settings.height))
             .build(&event_loop)
             .expect("Could not create window");
 
@@ -44,25 +31,31 @@ impl App {
 
         let surface = unsafe { instance.create_surface(&window).unwrap() };
 
-        let adapter = wgpu::util::initialize_adapter_from_env_or_default(
+        let adapter = match wgpu::util::initialize_adapter_from_env_or_default(
             &instance,
             wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all),
             Some(&surface),
         )
         .await
-        .expect("No suitable GPU adapters found");
+        {
+            Some(adapter) => adapter,
+            None => match request_fallback_adapter(&instance, Some(&surface)).await {
+                Some(adapter) => adapter,
+                None => {
+                    // There is no way to present without wgpu, but the CPU
+                    // simulation can still render frames headless.
+                    log::error!(
+                        "No suitable GPU or fallback adapters found; \
+                         run headless with --frames to simulate on the CPU"
+                    );
+                    std::process::exit(1);
+                }
+            },
+        };
+
+        log::info!("Using adapter {}", adapter.get_info().name);
 
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: Some("slime::device"),
-                    features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                    limits: Limits::default(),
-                },
-                None,
-            )
-            .await
-            .expect("Unable to get gpu device");
+        let (device, queue) = request_device(&adapter).await;
 
         App {
             settings,
@@ -76,3 +69,49 @@ impl App {
         }
     }
 }
+
+/// A software adapter, for machines without a usable GPU.
+pub async fn request_fallback_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> Option<wgpu::Adapter> {
+    instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface,
+        })
+        .await
+}
+
+/// Whether `adapter` can run the compute shaders the GPU backend needs.
+pub fn supports_compute(adapter: &wgpu::Adapter) -> bool {
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        && adapter.limits().max_storage_textures_per_shader_stage > 0
+}
+
+/// Opens `adapter` with everything it can give, so large populations get
+/// its full buffer sizes. Without compute shaders that is only what the CPU
+/// backend needs to present.
+pub async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+    let limits = if supports_compute(adapter) {
+        adapter.limits()
+    } else {
+        Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits())
+    };
+
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("slime::device"),
+                features: Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES & adapter.features(),
+                limits,
+            },
+            None,
+        )
+        .await
+        .expect("Unable to get gpu device")
+}
@@ -0,0 +1,178 @@
+//! The mouse brush. The cursor is tracked from window events, mapped into
+//! the simulation through the fit transform, and handed to the simulation
+//! each frame as a stroke that applies to every step while it is held.
+
+use bytemuck::{Pod, Zeroable};
+use winit::event::{ElementState, ModifiersState, MouseButton, WindowEvent};
+
+use crate::{
+    pipeline::{self, Viewport},
+    settings::AppSettings,
+};
+
+/// Attractant painted per second at the center of the brush, at strength 1.
+const PAINT_RATE: f32 = 20.0;
+
+/// Added to a sensor's weight at the center of the attractor, at strength 1.
+/// Matches the pull of a wall texel, so the cursor can outweigh a trail.
+const ATTRACTOR_WEIGHT: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Paint,
+    Erase,
+    Attract,
+    Repel,
+    Spawn,
+    Delete,
+}
+
+/// A held brush, in pixels of the simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stroke {
+    pub position: [f32; 2],
+    pub tool: Tool,
+}
+
+/// The brush as the shaders read it. Its effect falls off linearly from
+/// `amount` at `position` to nothing at `radius`; an `amount` of zero turns
+/// it off.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct BrushBuffer {
+    pub position: [f32; 2],
+    pub radius: f32,
+    pub amount: f32,
+    /// One for each trail channel the brush paints.
+    pub channels: [f32; 4],
+}
+
+impl BrushBuffer {
+    /// Attractant added or removed per second by `stroke`, in the channels
+    /// of every species.
+    pub fn paint(stroke: Option<Stroke>, settings: &AppSettings) -> Self {
+        match stroke {
+            Some(stroke) if stroke.tool == Tool::Paint => Self::new(stroke, settings, PAINT_RATE),
+            Some(stroke) if stroke.tool == Tool::Erase => Self::new(stroke, settings, -PAINT_RATE),
+            _ => Self::default(),
+        }
+    }
+
+    /// The weight `stroke` adds to the agents' sensors, negative to repel.
+    pub fn attractor(stroke: Option<Stroke>, settings: &AppSettings) -> Self {
+        match stroke {
+            Some(stroke) if stroke.tool == Tool::Attract => {
+                Self::new(stroke, settings, ATTRACTOR_WEIGHT)
+            }
+            Some(stroke) if stroke.tool == Tool::Repel => {
+                Self::new(stroke, settings, -ATTRACTOR_WEIGHT)
+            }
+            _ => Self::default(),
+        }
+    }
+
+    /// The circle `stroke` deletes agents in.
+    pub fn eraser(stroke: Option<Stroke>, settings: &AppSettings) -> Self {
+        match stroke {
+            Some(stroke) if stroke.tool == Tool::Delete => Self::new(stroke, settings, 1.0),
+            _ => Self::default(),
+        }
+    }
+
+    fn new(stroke: Stroke, settings: &AppSettings, scale: f32) -> Self {
+        Self {
+            position: stroke.position,
+            radius: settings.brush.radius,
+            amount: scale * settings.brush.strength,
+            channels: std::array::from_fn(|channel| {
+                (channel < settings.species.len()) as u32 as f32
+            }),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.amount != 0.0
+    }
+
+    /// How much of `amount` reaches `position`.
+    pub fn falloff(&self, position: [f32; 2]) -> f32 {
+        (1.0 - self.distance(position) / self.radius).max(0.0)
+    }
+
+    pub fn contains(&self, position: [f32; 2]) -> bool {
+        self.distance(position) <= self.radius
+    }
+
+    fn distance(&self, position: [f32; 2]) -> f32 {
+        let dx = position[0] - self.position[0];
+        let dy = position[1] - self.position[1];
+
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+/// The cursor and the buttons held down over the simulation.
+#[derive(Debug, Default)]
+pub struct Pointer {
+    /// In physical pixels of the window.
+    position: Option<[f32; 2]>,
+    left: bool,
+    right: bool,
+}
+
+impl Pointer {
+    /// Follows a window event. Presses the panel `consumed` do not start a
+    /// stroke, but releases always end one, wherever they happen.
+    pub fn handle_event(&mut self, event: &WindowEvent, consumed: bool) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.position = Some([position.x as f32, position.y as f32]);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.position = None;
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+
+                if pressed && consumed {
+                    return;
+                }
+
+                match button {
+                    MouseButton::Left => self.left = pressed,
+                    MouseButton::Right => self.right = pressed,
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The stroke under the cursor, if a button is held over the drawn
+    /// simulation. Shift turns the paint brush into the attractor and Ctrl
+    /// into the spawner.
+    pub fn stroke(
+        &self,
+        settings: &AppSettings,
+        viewport: &Viewport,
+        modifiers: ModifiersState,
+    ) -> Option<Stroke> {
+        let (add, remove) = if modifiers.ctrl() {
+            (Tool::Spawn, Tool::Delete)
+        } else if modifiers.shift() {
+            (Tool::Attract, Tool::Repel)
+        } else {
+            (Tool::Paint, Tool::Erase)
+        };
+
+        let tool = match (self.left, self.right) {
+            (true, _) => add,
+            (false, true) => remove,
+            (false, false) => return None,
+        };
+
+        let position = pipeline::window_to_simulation(settings, viewport, self.position?)?;
+
+        Some(Stroke { position, tool })
+    }
+}
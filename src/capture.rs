@@ -0,0 +1,229 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::pipeline::{Pipeline, RenderPipeline, Viewport};
+
+/// Copies `texture` back to the CPU and returns its texels as tightly packed
+/// rows, starting from the top. The texture needs `COPY_SRC` usage.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    bytes_per_pixel: u32,
+) -> Vec<u8> {
+    let size = texture.size();
+    let unpadded_bytes_per_row = size.width * bytes_per_pixel;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("slime::capture::readback_buffer"),
+        size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+    command_encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
+            },
+        },
+        size,
+    );
+
+    queue.submit(Some(command_encoder.finish()));
+
+    let data = map_readback(device, &buffer);
+
+    data.chunks(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+/// Copies `buffer` back to the CPU. The buffer needs `COPY_SRC` usage.
+pub fn read_buffer(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let readback = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("slime::capture::readback_buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    command_encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, buffer.size());
+    queue.submit(Some(command_encoder.finish()));
+
+    map_readback(device, &readback)
+}
+
+/// Waits for the GPU to finish writing `buffer` and copies out its contents.
+fn map_readback(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).ok();
+    });
+    device.poll(wgpu::Maintain::Wait);
+
+    receiver
+        .recv()
+        .expect("Readback was dropped")
+        .expect("Could not map readback buffer");
+
+    let data = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+
+    data
+}
+
+/// Copies a buffer back to the CPU without waiting for the GPU, for values
+/// the simulation can carry on without for a frame or two.
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    mapped: Option<mpsc::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl Readback {
+    /// Room for a copy of `size` bytes.
+    pub fn new(device: &wgpu::Device, size: wgpu::BufferAddress) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("slime::capture::async_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            buffer,
+            mapped: None,
+        }
+    }
+
+    /// Whether a copy is on its way.
+    pub fn is_pending(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    /// Starts copying `source`, which needs `COPY_SRC` usage, as it is once
+    /// the work submitted so far is done. Returns `false` when the previous
+    /// copy has not arrived yet.
+    pub fn start(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        source: &wgpu::Buffer,
+    ) -> bool {
+        if self.is_pending() {
+            return false;
+        }
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        command_encoder.copy_buffer_to_buffer(source, 0, &self.buffer, 0, self.buffer.size());
+        queue.submit(Some(command_encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                sender.send(result).ok();
+            });
+        self.mapped = Some(receiver);
+
+        true
+    }
+
+    /// The contents of the last copy, once it has arrived.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<Vec<u8>> {
+        device.poll(wgpu::Maintain::Poll);
+
+        let result = self.mapped.as_ref()?.try_recv().ok()?;
+        self.mapped = None;
+
+        if let Err(error) = result {
+            log::warn!("Could not map readback buffer: {}", error);
+            return None;
+        }
+
+        let data = self.buffer.slice(..).get_mapped_range().to_vec();
+        self.buffer.unmap();
+
+        Some(data)
+    }
+}
+
+/// Draws `render_pipeline` again into an offscreen texture the size of
+/// `viewport` and reads it back as an 8-bit image.
+pub fn screenshot(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    render_pipeline: &RenderPipeline,
+    format: wgpu::TextureFormat,
+    viewport: Viewport,
+) -> Option<image::RgbaImage> {
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("slime::capture::screenshot"),
+        size: wgpu::Extent3d {
+            width: viewport.width,
+            height: viewport.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let mut command_encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    render_pipeline.execute(&mut command_encoder, &view);
+    queue.submit(Some(command_encoder.finish()));
+
+    let texels = read_texture(device, queue, &target, 4);
+    let pixels = to_rgba8(format, texels)?;
+
+    image::RgbaImage::from_raw(viewport.width, viewport.height, pixels)
+}
+
+/// Converts texels read back from an 8-bit color texture to RGBA, or `None`
+/// for formats that are not 8 bits per channel.
+pub fn to_rgba8(format: wgpu::TextureFormat, mut texels: Vec<u8>) -> Option<Vec<u8>> {
+    match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => Some(texels),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for texel in texels.chunks_exact_mut(4) {
+                texel.swap(0, 2);
+            }
+
+            Some(texels)
+        }
+        _ => None,
+    }
+}
+
+/// A file name in the working directory that is unique per millisecond,
+/// e.g. `slime-1700000000000.png`.
+pub fn timestamped_path(extension: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis())
+        .unwrap_or_default();
+
+    PathBuf::from(format!("slime-{}.{}", millis, extension))
+}
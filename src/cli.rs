@@ -0,0 +1,347 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, ValueEnum};
+
+use crate::{
+    headless::HeadlessOptions,
+    settings::{
+        AppSettings, BoundaryMode, Facing, Fit, InitImage, ObstacleImage, SettingsError, SpawnMode,
+    },
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "slime", about = "GPU slime mould simulation")]
+pub struct Cli {
+    /// Settings file (.toml, .json or .ron) applied before any overrides
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Resume from a snapshot saved with F5; its settings replace the config
+    /// file and flags
+    #[arg(long, value_name = "PATH")]
+    pub load: Option<PathBuf>,
+
+    /// Run without a window, rendering this many frames to `--out`
+    #[arg(long, value_name = "N")]
+    pub frames: Option<usize>,
+
+    /// Save every Nth frame when running headless; by default only the final
+    /// frame is saved
+    #[arg(long, value_name = "N", requires = "frames",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    pub every: Option<u64>,
+
+    /// Directory headless frames are written to
+    #[arg(long, value_name = "DIR", default_value = "frames")]
+    pub out: PathBuf,
+
+    /// Simulate on the CPU instead of with compute shaders; the default
+    /// when the adapter has no compute support or there is no adapter
+    #[arg(long)]
+    pub cpu: bool,
+
+    #[command(flatten)]
+    pub overrides: SettingsOverrides,
+}
+
+/// Per-field overrides, applied on top of the defaults or the config file.
+/// Species fields apply to every configured species.
+#[derive(Debug, Default, Args)]
+pub struct SettingsOverrides {
+    #[arg(long)]
+    pub width: Option<u32>,
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// How the simulation fits a window of a different aspect ratio
+    #[arg(long, value_enum)]
+    pub fit: Option<Fit>,
+    /// What agents do at the edge of the map; torus makes tileable textures
+    #[arg(long, value_enum)]
+    pub boundary: Option<BoundaryMode>,
+    /// Rebuild the simulation at the window size when the window is resized
+    #[arg(long)]
+    pub resize_with_window: bool,
+    #[arg(long)]
+    pub num_agents: Option<usize>,
+    /// Simulation steps per second of real time
+    #[arg(long)]
+    pub sim_rate: Option<f32>,
+    /// Speed multiplier, e.g. 0.25 for slow motion or 4 to fast-forward
+    #[arg(long)]
+    pub time_scale: Option<f32>,
+    #[arg(long)]
+    pub max_steps_per_frame: Option<usize>,
+    /// Steps taken by the frame-advance key (Shift + .)
+    #[arg(long)]
+    pub frame_advance: Option<usize>,
+    /// Seed for a reproducible run
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub move_speed: Option<f32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub turn_speed: Option<f32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub sensor_angle_degrees: Option<f32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub sensor_offset_dst: Option<f32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub sensor_size: Option<i32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub trail_weight: Option<f32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub decay_rate: Option<f32>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub diffuse_rate: Option<f32>,
+    #[arg(long)]
+    pub agents_only: Option<bool>,
+    /// Agent spawn pattern
+    #[arg(long, value_enum)]
+    pub spawn: Option<SpawnKind>,
+    /// Radius of the circle and ring spawn patterns, in pixels
+    #[arg(long)]
+    pub spawn_radius: Option<f32>,
+    /// Width of the rect spawn pattern, in pixels
+    #[arg(long)]
+    pub spawn_width: Option<f32>,
+    /// Height of the rect spawn pattern, in pixels
+    #[arg(long)]
+    pub spawn_height: Option<f32>,
+    /// PNG that agents spawn on, proportionally to its brightness
+    #[arg(long, value_name = "PATH")]
+    pub init_image: Option<PathBuf>,
+    /// Spawn each species from its own channel of the init image
+    #[arg(long)]
+    pub init_image_per_species: bool,
+    /// Also pre-fill the trail map from the init image
+    #[arg(long)]
+    pub init_image_trails: bool,
+    /// PNG whose bright pixels are walls agents cannot pass
+    #[arg(long, value_name = "PATH")]
+    pub obstacles: Option<PathBuf>,
+    /// Treat the obstacle image's dark pixels as walls instead
+    #[arg(long)]
+    pub invert_obstacles: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SpawnKind {
+    Point,
+    Random,
+    Circle,
+    RingInward,
+    RingOutward,
+    Rect,
+}
+
+impl From<SpawnKind> for SpawnMode {
+    fn from(kind: SpawnKind) -> Self {
+        let extent = SpawnMode::DEFAULT_RADIUS;
+
+        match kind {
+            SpawnKind::Point => SpawnMode::Point,
+            SpawnKind::Random => SpawnMode::Random,
+            SpawnKind::Circle => SpawnMode::Circle { radius: extent },
+            SpawnKind::RingInward => SpawnMode::Ring {
+                radius: extent,
+                facing: Facing::Inward,
+            },
+            SpawnKind::RingOutward => SpawnMode::Ring {
+                radius: extent,
+                facing: Facing::Outward,
+            },
+            SpawnKind::Rect => SpawnMode::Rect {
+                width: extent * 2.0,
+                height: extent * 2.0,
+            },
+        }
+    }
+}
+
+impl SettingsOverrides {
+    pub fn apply(&self, settings: &mut AppSettings) {
+        macro_rules! apply {
+            ($target:expr; $($field:ident),* $(,)?) => {
+                $(
+                    if let Some(value) = self.$field {
+                        $target.$field = value;
+                    }
+                )*
+            };
+        }
+
+        apply!(
+            settings;
+            width,
+            height,
+            fit,
+            boundary,
+            num_agents,
+            sim_rate,
+            time_scale,
+            max_steps_per_frame,
+            frame_advance,
+            trail_weight,
+            decay_rate,
+            diffuse_rate,
+            agents_only,
+        );
+
+        settings.resize_with_window |= self.resize_with_window;
+
+        if let Some(seed) = self.seed {
+            settings.seed = Some(seed);
+        }
+
+        if let Some(kind) = self.spawn {
+            settings.spawn = kind.into();
+        }
+
+        match &mut settings.spawn {
+            SpawnMode::Circle { radius } | SpawnMode::Ring { radius, .. } => {
+                *radius = self.spawn_radius.unwrap_or(*radius);
+            }
+            SpawnMode::Rect { width, height } => {
+                *width = self.spawn_width.unwrap_or(*width);
+                *height = self.spawn_height.unwrap_or(*height);
+            }
+            SpawnMode::Point | SpawnMode::Random => {}
+        }
+
+        if let Some(path) = &self.init_image {
+            settings.init_image = Some(InitImage::new(path.clone()));
+        }
+
+        if let Some(image) = &mut settings.init_image {
+            image.per_species |= self.init_image_per_species;
+            image.seed_trails |= self.init_image_trails;
+        }
+
+        if let Some(path) = &self.obstacles {
+            settings.obstacles = Some(ObstacleImage::new(path.clone()));
+        }
+
+        if let Some(image) = &mut settings.obstacles {
+            image.invert |= self.invert_obstacles;
+        }
+
+        for species in &mut settings.species {
+            apply!(
+                species;
+                move_speed,
+                turn_speed,
+                sensor_angle_degrees,
+                sensor_offset_dst,
+                sensor_size,
+            );
+        }
+    }
+}
+
+impl Cli {
+    /// Options for a headless run, if `--frames` was given.
+    pub fn headless(&self) -> Option<HeadlessOptions> {
+        self.frames.map(|frames| HeadlessOptions {
+            frames,
+            every: self.every.map(|every| every as usize),
+            out: self.out.clone(),
+            cpu: self.cpu,
+        })
+    }
+
+    /// Resolves the final settings: defaults, then the config file, then flags.
+    pub fn settings(&self) -> Result<AppSettings, SettingsError> {
+        let mut settings = match &self.config {
+            Some(path) => AppSettings::load(path)?,
+            None => AppSettings::default(),
+        };
+
+        self.overrides.apply(&mut settings);
+        settings.validate()?;
+
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::settings::tests::write_temp;
+
+    fn parse(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("slime").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn flags_override_the_config_file() {
+        let path = write_temp(
+            "overridden.toml",
+            r#"
+                width = 320
+                height = 240
+                num_agents = 1000
+                seed = 1
+                spawn = { mode = "circle", radius = 50.0 }
+
+                [[species]]
+                move_speed = 20.0
+
+                [[species]]
+                move_speed = 30.0
+            "#,
+        );
+
+        let settings = parse(&[
+            "--config",
+            path.to_str().unwrap(),
+            "--width",
+            "640",
+            "--seed",
+            "2",
+            "--spawn-radius",
+            "80",
+            "--move-speed",
+            "-5",
+        ])
+        .settings()
+        .unwrap();
+
+        assert_eq!(settings.width, 640);
+        assert_eq!(settings.height, 240);
+        assert_eq!(settings.num_agents, 1000);
+        assert_eq!(settings.seed, Some(2));
+        assert_eq!(settings.spawn, SpawnMode::Circle { radius: 80.0 });
+
+        for species in &settings.species {
+            assert_eq!(species.move_speed, -5.0);
+        }
+    }
+
+    #[test]
+    fn config_values_survive_without_flags() {
+        let path = write_temp("kept.toml", "width = 320\nboundary = \"torus\"");
+
+        let settings = parse(&["--config", path.to_str().unwrap()])
+            .settings()
+            .unwrap();
+
+        assert_eq!(settings.width, 320);
+        assert_eq!(settings.boundary, BoundaryMode::Torus);
+        assert_eq!(settings.height, AppSettings::default().height);
+    }
+
+    #[test]
+    fn invalid_overrides_are_rejected() {
+        let result = parse(&["--num-agents", "0"]).settings();
+
+        assert!(matches!(
+            result,
+            Err(SettingsError::Invalid {
+                field: "num_agents",
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::settings::AppSettings;
+
+/// Turns real time into whole simulation steps at `sim_rate`, carrying the
+/// remainder over to the next frame. While paused, only steps queued with
+/// `step` are taken.
+#[derive(Default)]
+pub struct StepClock {
+    /// Real time, scaled by `time_scale`, not yet simulated.
+    accumulator: f32,
+    paused: bool,
+    pending_steps: usize,
+}
+
+impl StepClock {
+    /// Adds `elapsed` seconds and takes out as many whole steps as the
+    /// accumulator holds, up to `max_steps_per_frame`.
+    pub fn advance(&mut self, settings: &AppSettings, elapsed: f32) -> usize {
+        if self.paused {
+            let steps = self.pending_steps.min(settings.max_steps_per_frame);
+            self.pending_steps -= steps;
+
+            return steps;
+        }
+
+        let step_time = 1.0 / settings.sim_rate;
+
+        self.accumulator += elapsed * settings.time_scale;
+
+        let steps = (self.accumulator / step_time) as usize;
+        self.accumulator -= steps as f32 * step_time;
+
+        if steps > settings.max_steps_per_frame {
+            log::debug!(
+                "Dropping {} simulation steps to keep up",
+                steps - settings.max_steps_per_frame
+            );
+        }
+
+        steps.min(settings.max_steps_per_frame)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+
+        // Time spent paused is not caught up on resume.
+        self.accumulator = 0.0;
+    }
+
+    /// Pauses and queues `count` steps, taken over the next frames at up to
+    /// `max_steps_per_frame` each.
+    pub fn step(&mut self, count: usize) {
+        self.paused = true;
+        self.pending_steps += count;
+    }
+}
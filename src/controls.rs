@@ -0,0 +1,146 @@
+use winit::event::VirtualKeyCode;
+
+use crate::settings::{AppSettings, SpeciesSettings, MAX_AGENTS};
+
+#[derive(Clone, Copy, Debug)]
+enum Parameter {
+    MoveSpeed,
+    TurnSpeed,
+    SensorAngle,
+    SensorOffset,
+    TrailWeight,
+    DecayRate,
+    DiffuseRate,
+    TimeScale,
+}
+
+impl Parameter {
+    /// Each parameter is bound to a key pair on adjacent keyboard rows, the
+    /// top row nudging it up and the one below nudging it down.
+    fn from_key(key: VirtualKeyCode) -> Option<(Parameter, f32)> {
+        let binding = match key {
+            VirtualKeyCode::Q => (Parameter::MoveSpeed, 1.0),
+            VirtualKeyCode::A => (Parameter::MoveSpeed, -1.0),
+            VirtualKeyCode::W => (Parameter::TurnSpeed, 1.0),
+            VirtualKeyCode::S => (Parameter::TurnSpeed, -1.0),
+            VirtualKeyCode::E => (Parameter::SensorAngle, 1.0),
+            VirtualKeyCode::D => (Parameter::SensorAngle, -1.0),
+            VirtualKeyCode::R => (Parameter::SensorOffset, 1.0),
+            VirtualKeyCode::F => (Parameter::SensorOffset, -1.0),
+            VirtualKeyCode::T => (Parameter::TrailWeight, 1.0),
+            VirtualKeyCode::G => (Parameter::TrailWeight, -1.0),
+            VirtualKeyCode::Y => (Parameter::DecayRate, 1.0),
+            VirtualKeyCode::H => (Parameter::DecayRate, -1.0),
+            VirtualKeyCode::U => (Parameter::DiffuseRate, 1.0),
+            VirtualKeyCode::J => (Parameter::DiffuseRate, -1.0),
+            VirtualKeyCode::I => (Parameter::TimeScale, 1.0),
+            VirtualKeyCode::K => (Parameter::TimeScale, -1.0),
+            _ => return None,
+        };
+
+        Some(binding)
+    }
+
+    /// Every value this parameter controls. Species parameters are nudged
+    /// for all species at once.
+    fn values(self, settings: &mut AppSettings) -> Vec<&mut f32> {
+        match self {
+            Parameter::MoveSpeed => species_values(settings, |species| &mut species.move_speed),
+            Parameter::TurnSpeed => species_values(settings, |species| &mut species.turn_speed),
+            Parameter::SensorAngle => {
+                species_values(settings, |species| &mut species.sensor_angle_degrees)
+            }
+            Parameter::SensorOffset => {
+                species_values(settings, |species| &mut species.sensor_offset_dst)
+            }
+            Parameter::TrailWeight => vec![&mut settings.trail_weight],
+            Parameter::DecayRate => vec![&mut settings.decay_rate],
+            Parameter::DiffuseRate => vec![&mut settings.diffuse_rate],
+            Parameter::TimeScale => vec![&mut settings.time_scale],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Parameter::MoveSpeed => "move_speed",
+            Parameter::TurnSpeed => "turn_speed",
+            Parameter::SensorAngle => "sensor_angle_degrees",
+            Parameter::SensorOffset => "sensor_offset_dst",
+            Parameter::TrailWeight => "trail_weight",
+            Parameter::DecayRate => "decay_rate",
+            Parameter::DiffuseRate => "diffuse_rate",
+            Parameter::TimeScale => "time_scale",
+        }
+    }
+
+    fn step(self) -> f32 {
+        match self {
+            Parameter::MoveSpeed => 5.0,
+            Parameter::TurnSpeed => 0.25,
+            Parameter::SensorAngle => 2.0,
+            Parameter::SensorOffset => 1.0,
+            Parameter::TrailWeight => 0.25,
+            Parameter::DecayRate => 0.05,
+            Parameter::DiffuseRate => 0.5,
+            Parameter::TimeScale => 0.25,
+        }
+    }
+
+    fn min(self) -> f32 {
+        match self {
+            Parameter::TurnSpeed => f32::MIN,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Applies the nudge bound to `key`, returning a description of the new value.
+/// Holding shift makes the step ten times larger.
+pub fn nudge(settings: &mut AppSettings, key: VirtualKeyCode, shift: bool) -> Option<String> {
+    let (parameter, direction) = Parameter::from_key(key)?;
+
+    let step = if shift {
+        parameter.step() * 10.0
+    } else {
+        parameter.step()
+    };
+
+    let values = parameter
+        .values(settings)
+        .into_iter()
+        .map(|value| {
+            *value = (*value + direction * step).max(parameter.min());
+            value.to_string()
+        })
+        .collect::<Vec<_>>();
+
+    Some(format!("{} = {}", parameter.name(), values.join(", ")))
+}
+
+/// The agent count `key` asks for, starting from `count`: `=` adds a tenth
+/// and `-` takes one away, or with shift they double and halve it.
+pub fn agent_count(count: usize, key: VirtualKeyCode, shift: bool) -> Option<usize> {
+    let grow = match key {
+        VirtualKeyCode::Equals | VirtualKeyCode::NumpadAdd => true,
+        VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => false,
+        _ => return None,
+    };
+
+    let factor = if shift { 2.0 } else { 1.1 };
+
+    // At least one agent either way, so small populations still change.
+    let count = if grow {
+        ((count as f64 * factor).round() as usize).max(count + 1)
+    } else {
+        ((count as f64 / factor).round() as usize).min(count.saturating_sub(1))
+    };
+
+    Some(count.clamp(1, MAX_AGENTS))
+}
+
+fn species_values(
+    settings: &mut AppSettings,
+    field: fn(&mut SpeciesSettings) -> &mut f32,
+) -> Vec<&mut f32> {
+    settings.species.iter_mut().map(field).collect()
+}
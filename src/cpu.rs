@@ -0,0 +1,1353 @@
+//! A plain Rust port of `slime_sim.wgsl`, `deposit.wgsl`, `diffuse.wgsl`,
+//! `resample.wgsl` and `spawn_agents.wgsl`, kept step for step with the
+//! shaders.
+//! It is a reference for the GPU path and the backend for machines that
+//! cannot run compute shaders.
+
+use std::f32::consts::PI;
+
+use half::f16;
+use rayon::prelude::*;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    agents::{self, Slots},
+    brush::{BrushBuffer, Stroke, Tool},
+    food, obstacles,
+    pipeline::SpawnBuffer,
+    settings::{AppSettings, BoundaryMode, SpeciesSettings},
+    shader_pipeline::{self, Agent, STEP_DELTA_TIME},
+    snapshot::Snapshot,
+    spawn,
+};
+
+/// The truncated value of pi the shaders use, kept so results match.
+#[allow(clippy::approx_constant)]
+const SHADER_PI: f32 = 3.1415;
+
+/// Added to a sensor's weight for every wall texel it covers.
+const OBSTACLE_WEIGHT: f32 = -10.0;
+
+/// The color `draw.wgsl` draws walls in.
+const WALL_COLOR: [f32; 3] = [0.25, 0.25, 0.3];
+
+pub struct CpuSimulation {
+    settings: AppSettings,
+    /// The slots in use, dead agents included.
+    agents: Vec<Agent>,
+    /// Mirrors the books of the GPU buffer, so agent indices stay in step.
+    slots: Slots,
+    /// Trail map texels, row by row, rounded to half precision on every
+    /// write like the GPU texture.
+    trail_map: Vec<[f32; 4]>,
+    /// One byte per texel, non-zero on walls.
+    obstacles: Vec<u8>,
+    /// Attractant added per second, from `food::food_map`.
+    food_map: Vec<[f32; 4]>,
+    seed: u64,
+    step: u32,
+    stroke: Option<Stroke>,
+    /// Scratch space reused between steps.
+    deposits: Vec<Option<usize>>,
+    deposit_counts: Vec<u32>,
+    diffused: Vec<[f32; 4]>,
+}
+
+/// Everything an agent reads during a step.
+struct StepInputs<'a> {
+    settings: &'a AppSettings,
+    trail_map: &'a [[f32; 4]],
+    obstacles: &'a [u8],
+    attractor: BrushBuffer,
+    eraser: BrushBuffer,
+    seed_hash: u32,
+    step: u32,
+}
+
+impl CpuSimulation {
+    pub fn new(settings: AppSettings) -> Self {
+        Self::from_snapshot(&spawn::initial_snapshot(settings))
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let size = PhysicalSize::new(snapshot.settings.width, snapshot.settings.height);
+        let slots = Slots::for_agents(&snapshot.agents);
+
+        Self {
+            settings: AppSettings {
+                num_agents: slots.live(),
+                ..snapshot.settings.clone()
+            },
+            agents: snapshot.agents.clone(),
+            slots,
+            trail_map: snapshot
+                .trail_map
+                .iter()
+                .map(|texel| texel.map(f16::to_f32))
+                .collect(),
+            obstacles: snapshot.obstacles.clone(),
+            food_map: food::food_map(&snapshot.settings, &size),
+            seed: snapshot.seed,
+            step: snapshot.step,
+            stroke: None,
+            deposits: Vec::new(),
+            deposit_counts: Vec::new(),
+            diffused: Vec::new(),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            // A snapshot counts the slots it stores, dead agents included.
+            settings: AppSettings {
+                num_agents: self.agents.len(),
+                ..self.settings.clone()
+            },
+            seed: self.seed,
+            step: self.step,
+            agents: self.agents.clone(),
+            trail_map: self
+                .trail_map
+                .iter()
+                .map(|texel| texel.map(f16::from_f32))
+                .collect(),
+            obstacles: self.obstacles.clone(),
+        }
+    }
+
+    pub fn settings(&self) -> &AppSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: &AppSettings) {
+        let settings = self.settings.updated_with(settings);
+
+        if settings.food != self.settings.food {
+            let size = PhysicalSize::new(settings.width, settings.height);
+            self.food_map = food::food_map(&settings, &size);
+        }
+
+        self.settings = settings;
+    }
+
+    /// Grows or shrinks the population to `count` live agents, as
+    /// `ShaderPipeline` does.
+    pub fn set_agent_count(&mut self, count: usize) {
+        agents::resize(
+            &mut self.agents,
+            count,
+            &self.settings,
+            self.seed,
+            self.step,
+        );
+
+        log::info!(
+            "Changed agent count from {} to {}",
+            self.settings.num_agents,
+            self.agents.len()
+        );
+
+        self.slots = Slots::new(self.agents.len());
+        self.settings.num_agents = self.agents.len();
+    }
+
+    /// Holds the brush down at `stroke` for the steps to come, or lifts it.
+    pub fn set_brush(&mut self, stroke: Option<Stroke>) {
+        self.stroke = stroke;
+    }
+
+    pub fn obstacles(&self) -> &[u8] {
+        &self.obstacles
+    }
+
+    pub fn step_count(&self) -> u32 {
+        self.step
+    }
+
+    /// One tick of the fixed clock: move, deposit and feed, then blur,
+    /// decay and paint.
+    pub fn step(&mut self) {
+        let erasing = BrushBuffer::eraser(self.stroke, &self.settings).is_active();
+
+        if erasing {
+            self.slots.cull();
+        }
+
+        if let Some(stroke) = self.stroke.filter(|stroke| stroke.tool == Tool::Spawn) {
+            self.spawn_agents(stroke);
+        }
+
+        self.simulate();
+        self.diffuse(&BrushBuffer::paint(self.stroke, &self.settings));
+
+        if erasing {
+            let deleted = self
+                .agents
+                .par_iter()
+                .filter(|agent| !agents::is_alive(agent))
+                .count();
+
+            self.slots.counted(deleted);
+            self.settings.num_agents = self.slots.live();
+        }
+
+        self.step = self.step.wrapping_add(1);
+    }
+
+    /// `resample.wgsl`: bilinearly resamples the trail map to `size` and
+    /// scales agent positions to match. Walls are resampled as on the GPU
+    /// path, by `obstacles::resample`.
+    pub fn resize(&mut self, size: &PhysicalSize<u32>) {
+        let old_width = self.settings.width;
+        let old_height = self.settings.height;
+
+        let scale = [
+            size.width as f32 / old_width as f32,
+            size.height as f32 / old_height as f32,
+        ];
+
+        let trail_map = &self.trail_map;
+        let load_old = |x: i32, y: i32| {
+            let x = x.clamp(0, old_width as i32 - 1) as u32;
+            let y = y.clamp(0, old_height as i32 - 1) as u32;
+
+            trail_map[(y * old_width + x) as usize]
+        };
+
+        let resampled = (0..size.width * size.height)
+            .into_par_iter()
+            .map(|index| {
+                let source_x = ((index % size.width) as f32 + 0.5) / scale[0] - 0.5;
+                let source_y = ((index / size.width) as f32 + 0.5) / scale[1] - 0.5;
+
+                let (x, y) = (source_x.floor(), source_y.floor());
+                let (t_x, t_y) = (source_x - x, source_y - y);
+                let (x, y) = (x as i32, y as i32);
+
+                let top = mix(load_old(x, y), load_old(x + 1, y), t_x);
+                let bottom = mix(load_old(x, y + 1), load_old(x + 1, y + 1), t_x);
+
+                round_to_half(mix(top, bottom, t_y))
+            })
+            .collect();
+
+        self.trail_map = resampled;
+        self.obstacles = obstacles::resample(
+            &self.obstacles,
+            &PhysicalSize::new(old_width, old_height),
+            size,
+        );
+
+        let max_position = [size.width as f32 - 1.0, size.height as f32 - 1.0];
+
+        self.agents.par_iter_mut().for_each(|agent| {
+            for axis in 0..2 {
+                agent.position[axis] =
+                    (agent.position[axis] * scale[axis]).clamp(0.0, max_position[axis]);
+            }
+        });
+
+        self.settings.width = size.width;
+        self.settings.height = size.height;
+        self.food_map = food::food_map(&self.settings, size);
+    }
+
+    /// The texture the render pipeline draws: the trail map, or just the
+    /// agents when `agents_only` is set.
+    pub fn display(&self) -> Vec<[f16; 4]> {
+        if self.settings.agents_only {
+            let mut display = vec![[f16::ZERO; 4]; self.trail_map.len()];
+
+            for agent in self.agents.iter().filter(|agent| agents::is_alive(agent)) {
+                let x = agent.position[0] as usize;
+                let y = agent.position[1] as usize;
+                display[y * self.settings.width as usize + x] =
+                    species_mask(agent.species_index).map(f16::from_f32);
+            }
+
+            display
+        } else {
+            self.trail_map
+                .par_iter()
+                .map(|texel| texel.map(f16::from_f32))
+                .collect()
+        }
+    }
+
+    /// The display as the render pipeline draws it at the simulation size,
+    /// with row zero of the simulation at the bottom.
+    pub fn image(&self) -> image::RgbaImage {
+        let width = self.settings.width;
+        let height = self.settings.height;
+        let display = self.display();
+
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let index = ((height - 1 - y) * width + x) as usize;
+
+            let mut color = [0.0; 3];
+
+            for (value, species) in display[index].iter().zip(&self.settings.species) {
+                for (channel, tint) in color.iter_mut().zip(species.color) {
+                    *channel += value.to_f32() * tint;
+                }
+            }
+
+            if self.obstacles[index] != 0 {
+                color = WALL_COLOR;
+            }
+
+            let [r, g, b] = color.map(encode_srgb);
+            image::Rgba([r, g, b, 255])
+        })
+    }
+
+    /// The raw trail map, flipped so row zero of the simulation is at the
+    /// bottom.
+    pub fn trail_map_image(&self) -> image::Rgba32FImage {
+        let values = self.trail_map.iter().flatten().copied().collect();
+
+        let image =
+            image::Rgba32FImage::from_raw(self.settings.width, self.settings.height, values)
+                .expect("Trail map has the wrong size");
+
+        image::imageops::flip_vertical(&image)
+    }
+
+    /// Adds a burst from the spawn brush, compacting away deleted agents
+    /// first and growing the storage when the GPU buffer would.
+    fn spawn_agents(&mut self, stroke: Stroke) {
+        let burst = self.settings.brush.spawn_count;
+
+        if self.slots.needs_compaction(burst) {
+            agents::compact(&mut self.agents);
+            self.slots.compacted(self.agents.len());
+        }
+
+        if let Some(capacity) = self.slots.growth(burst) {
+            self.slots.grown(capacity);
+        }
+
+        let first_index = self.slots.count;
+        let count = self.slots.reserve(burst);
+
+        self.agents.extend(spawn::brush_agents(
+            &self.settings,
+            stroke.position,
+            first_index,
+            count,
+            &mut spawn::brush_rng(self.seed, self.step),
+        ));
+        self.settings.num_agents = self.slots.live();
+    }
+
+    /// `slime_sim.wgsl`: every agent senses the trail map as it was at the
+    /// start of the step, so agents move in parallel. `deposit.wgsl`: the
+    /// agents on each texel are then counted per species and added together
+    /// with the food map's attractant.
+    fn simulate(&mut self) {
+        let inputs = StepInputs {
+            settings: &self.settings,
+            trail_map: &self.trail_map,
+            obstacles: &self.obstacles,
+            attractor: BrushBuffer::attractor(self.stroke, &self.settings),
+            eraser: BrushBuffer::eraser(self.stroke, &self.settings),
+            seed_hash: hash(shader_pipeline::shader_seed(self.seed)),
+            step: self.step,
+        };
+
+        self.agents
+            .par_iter_mut()
+            .enumerate()
+            .map(|(index, agent)| update_agent(agent, index as u32, &inputs))
+            .collect_into_vec(&mut self.deposits);
+
+        self.deposit_counts.clear();
+        self.deposit_counts.resize(self.trail_map.len() * 4, 0);
+
+        for counter in self.deposits.drain(..).flatten() {
+            self.deposit_counts[counter] += 1;
+        }
+
+        let trail_weight = self.settings.trail_weight;
+
+        self.trail_map
+            .par_iter_mut()
+            .zip(self.deposit_counts.par_chunks(4))
+            .zip(self.food_map.par_iter())
+            .filter(|((_, counts), food)| {
+                counts.iter().any(|&count| count > 0) || food.iter().any(|&food| food > 0.0)
+            })
+            .for_each(|((texel, counts), food)| {
+                *texel = round_to_half(std::array::from_fn(|channel| {
+                    let deposit = counts[channel] as f32 * trail_weight * STEP_DELTA_TIME
+                        + food[channel] * STEP_DELTA_TIME;
+                    (texel[channel] + deposit).min(1.0)
+                }));
+            });
+    }
+
+    /// `diffuse.wgsl`: a 3x3 box blur mixed in by `diffuse_rate`, then a
+    /// linear decay, then the paint brush adds or removes attractant.
+    fn diffuse(&mut self, brush: &BrushBuffer) {
+        let width = self.settings.width;
+        let height = self.settings.height;
+
+        let diffuse_weight = (self.settings.diffuse_rate * STEP_DELTA_TIME).clamp(0.0, 1.0);
+        let decay = self.settings.decay_rate * STEP_DELTA_TIME;
+        let torus = self.settings.boundary == BoundaryMode::Torus;
+
+        let trail_map = &self.trail_map;
+        let obstacles = &self.obstacles;
+
+        self.diffused.resize(trail_map.len(), [0.0; 4]);
+        self.diffused
+            .par_chunks_mut(width as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let y = y as u32;
+
+                for (x, diffused) in (0..width).zip(row) {
+                    // Nothing lingers on walls.
+                    if obstacles[(y * width + x) as usize] != 0 {
+                        *diffused = [0.0; 4];
+                        continue;
+                    }
+
+                    let original_col = trail_map[(y * width + x) as usize];
+
+                    // Only open neighbours are averaged, so trails do not
+                    // bleed through walls.
+                    let mut count = 0.0;
+                    let mut sum = [0.0; 4];
+
+                    for offset_x in -1i32..=1 {
+                        for offset_y in -1i32..=1 {
+                            let (sample_x, sample_y) = if torus {
+                                (
+                                    (x + width).wrapping_add(offset_x as u32) % width,
+                                    (y + height).wrapping_add(offset_y as u32) % height,
+                                )
+                            } else {
+                                (
+                                    (width - 1).min(x.wrapping_add(offset_x as u32)),
+                                    (height - 1).min(y.wrapping_add(offset_y as u32)),
+                                )
+                            };
+
+                            let index = (sample_y * width + sample_x) as usize;
+
+                            if obstacles[index] == 0 {
+                                for (sum, value) in sum.iter_mut().zip(trail_map[index]) {
+                                    *sum += value;
+                                }
+
+                                count += 1.0;
+                            }
+                        }
+                    }
+
+                    let paint = if brush.is_active() {
+                        brush.amount
+                            * brush.falloff([x as f32 + 0.5, y as f32 + 0.5])
+                            * STEP_DELTA_TIME
+                    } else {
+                        0.0
+                    };
+
+                    *diffused = round_to_half(std::array::from_fn(|channel| {
+                        let blurred = sum[channel] / count;
+                        let mixed = original_col[channel] * (1.0 - diffuse_weight)
+                            + blurred * diffuse_weight;
+                        let decayed = (mixed - decay).max(0.0);
+
+                        if brush.is_active() {
+                            (decayed + brush.channels[channel] * paint).clamp(0.0, 1.0)
+                        } else {
+                            decayed
+                        }
+                    }));
+                }
+            });
+
+        std::mem::swap(&mut self.trail_map, &mut self.diffused);
+    }
+}
+
+/// Senses, steers and moves one agent, returning the deposit counter it adds
+/// to: its texel's channel for its species. Agents under the eraser are
+/// marked dead instead.
+fn update_agent(agent: &mut Agent, index: u32, inputs: &StepInputs) -> Option<usize> {
+    if !agents::is_alive(agent) {
+        return None;
+    }
+
+    if inputs.eraser.is_active() && inputs.eraser.contains(agent.position) {
+        agent.species_index = agents::DEAD;
+        return None;
+    }
+
+    let settings = inputs.settings;
+    let width = settings.width;
+    let global_width = settings.width as f32;
+    let global_height = settings.height as f32;
+
+    let species = &settings.species[agent.species_index as usize];
+
+    let random = hash(
+        (agent.position[1] as u32)
+            .wrapping_mul(width)
+            .wrapping_add(agent.position[0] as u32)
+            .wrapping_add(hash(
+                index
+                    .wrapping_add(inputs.step.wrapping_mul(100000))
+                    .wrapping_add(inputs.seed_hash),
+            )),
+    );
+
+    let sensor_angle_rad = species.sensor_angle_degrees * (SHADER_PI / 180.0);
+    let weight_forward = sense(inputs, agent, species, 0.0);
+    let weight_left = sense(inputs, agent, species, sensor_angle_rad);
+    let weight_right = sense(inputs, agent, species, -sensor_angle_rad);
+
+    let random_steer_strength = scale_to_range(random as f32);
+    let turn_speed = species.turn_speed * 2.0 * SHADER_PI;
+
+    // Movement uses the angle from before steering.
+    let angle = agent.angle;
+
+    if weight_forward > weight_left && weight_forward > weight_right {
+        agent.angle = angle + 0.0;
+    } else if weight_forward < weight_left && weight_forward < weight_right {
+        agent.angle = angle + (random_steer_strength - 0.5) * 2.0 * turn_speed * STEP_DELTA_TIME;
+    } else if weight_right > weight_left {
+        agent.angle = angle - random_steer_strength * turn_speed * STEP_DELTA_TIME;
+    } else if weight_left > weight_right {
+        agent.angle = angle + random_steer_strength * turn_speed * STEP_DELTA_TIME;
+    }
+
+    let mut new_pos = [
+        agent.position[0] + angle.cos() * STEP_DELTA_TIME * species.move_speed,
+        agent.position[1] + angle.sin() * STEP_DELTA_TIME * species.move_speed,
+    ];
+
+    let size = [global_width, global_height];
+
+    // Agents that stay on the map deposit, as do those wrapped or reflected
+    // back onto it.
+    let mut deposit = true;
+
+    let new_rand = hash(random);
+    let random_angle = scale_to_range(new_rand as f32) * 2.0 * SHADER_PI;
+
+    if new_pos[0] < 0.0
+        || new_pos[0] >= global_width
+        || new_pos[1] < 0.0
+        || new_pos[1] >= global_height
+    {
+        match settings.boundary {
+            BoundaryMode::Torus => {
+                new_pos = std::array::from_fn(|axis| {
+                    let wrapped = new_pos[axis] - (new_pos[axis] / size[axis]).floor() * size[axis];
+
+                    // A tiny negative position can round up to the far edge.
+                    if wrapped >= size[axis] {
+                        0.0
+                    } else {
+                        wrapped
+                    }
+                });
+            }
+            BoundaryMode::Reflect => {
+                if new_pos[0] < 0.0 || new_pos[0] >= global_width {
+                    new_pos[0] = if new_pos[0] >= global_width {
+                        2.0 * global_width - new_pos[0]
+                    } else {
+                        -new_pos[0]
+                    };
+                    agent.angle = std::f32::consts::PI - agent.angle;
+                }
+                if new_pos[1] < 0.0 || new_pos[1] >= global_height {
+                    new_pos[1] = if new_pos[1] >= global_height {
+                        2.0 * global_height - new_pos[1]
+                    } else {
+                        -new_pos[1]
+                    };
+                    agent.angle = -agent.angle;
+                }
+
+                new_pos = std::array::from_fn(|axis| new_pos[axis].clamp(0.0, size[axis] - 1.0));
+            }
+            BoundaryMode::RespawnRandom => {
+                let x_rand = hash(new_rand);
+                let y_rand = hash(x_rand);
+
+                new_pos = [
+                    scale_to_range(x_rand as f32) * (global_width - 1.0),
+                    scale_to_range(y_rand as f32) * (global_height - 1.0),
+                ];
+                agent.angle = random_angle;
+                deposit = false;
+            }
+            BoundaryMode::RespawnCenter => {
+                new_pos = [global_width * 0.5, global_height * 0.5];
+                agent.angle = random_angle;
+                deposit = false;
+            }
+            BoundaryMode::Clamp => {
+                new_pos[0] = (global_width - 1.0).min(new_pos[0].max(0.0));
+                new_pos[1] = (global_height - 1.0).min(new_pos[1].max(0.0));
+                agent.angle = random_angle;
+                deposit = false;
+            }
+        }
+    }
+
+    let texel_at =
+        |position: [f32; 2]| position[1] as usize * width as usize + position[0] as usize;
+    let texel = texel_at(new_pos);
+
+    // Walls stop agents coming from open space and turn them, like the
+    // clamped edge. Agents that start inside a wall can walk out of it.
+    if inputs.obstacles[texel] != 0 {
+        if inputs.obstacles[texel_at(agent.position)] == 0 {
+            new_pos = agent.position;
+            agent.angle = random_angle;
+        }
+
+        deposit = false;
+    }
+
+    let counter = deposit.then(|| texel * 4 + agent.species_index as usize);
+
+    agent.position = new_pos;
+
+    counter
+}
+
+/// Sums the trail map around a sensor `sensor_angle_offset` from the
+/// agent's heading, counting its own species' channel for and the others
+/// against, plus the pull of the cursor attractor.
+///
+/// Coordinates wrap as unsigned integers before being clamped, so a sensor
+/// in column or row zero also samples the far edge, as on the GPU.
+fn sense(
+    inputs: &StepInputs,
+    agent: &Agent,
+    species: &SpeciesSettings,
+    sensor_angle_offset: f32,
+) -> f32 {
+    let width = inputs.settings.width;
+    let height = inputs.settings.height;
+
+    let sensor_angle = agent.angle + sensor_angle_offset;
+
+    let sensor_x = agent.position[0] + sensor_angle.cos() * species.sensor_offset_dst;
+    let sensor_y = agent.position[1] + sensor_angle.sin() * species.sensor_offset_dst;
+
+    let sensor_center_x = sensor_x as u32;
+    let sensor_center_y = sensor_y as u32;
+    let torus = inputs.settings.boundary == BoundaryMode::Torus;
+
+    let mask = species_mask(agent.species_index).map(|value| value * 2.0 - 1.0);
+
+    let mut sum = 0.0;
+
+    for offset_x in -species.sensor_size..=species.sensor_size {
+        for offset_y in -species.sensor_size..=species.sensor_size {
+            let (sample_x, sample_y) = if torus {
+                (
+                    (sensor_x.floor() as i32 + offset_x).rem_euclid(width as i32) as u32,
+                    (sensor_y.floor() as i32 + offset_y).rem_euclid(height as i32) as u32,
+                )
+            } else {
+                (
+                    (width - 1).min(sensor_center_x.wrapping_add(offset_x as u32)),
+                    (height - 1).min(sensor_center_y.wrapping_add(offset_y as u32)),
+                )
+            };
+
+            let texel = (sample_y * width + sample_x) as usize;
+
+            if inputs.obstacles[texel] != 0 {
+                sum += OBSTACLE_WEIGHT;
+            } else {
+                let current_map = inputs.trail_map[texel];
+
+                sum += mask[0] * current_map[0]
+                    + mask[1] * current_map[1]
+                    + mask[2] * current_map[2]
+                    + mask[3] * current_map[3];
+            }
+        }
+    }
+
+    if inputs.attractor.is_active() {
+        sum += inputs.attractor.amount * inputs.attractor.falloff([sensor_x, sensor_y]);
+    }
+
+    sum
+}
+
+/// `spawn_agents.wgsl`: places the agents `spawn` asks for, each hashed from
+/// the seed and its index.
+pub fn spawned_agents(spawn: &SpawnBuffer) -> Vec<Agent> {
+    let first_index = spawn.first_index;
+
+    (first_index..first_index + spawn.num_agents)
+        .into_par_iter()
+        .map(|index| spawned_agent(spawn, index))
+        .collect()
+}
+
+fn spawned_agent(spawn: &SpawnBuffer, index: u32) -> Agent {
+    let state0 = hash(index ^ hash(spawn.seed));
+    let state1 = hash(state0);
+    let state2 = hash(state1);
+    let draws = [state0, state1, state2].map(|state| scale_to_range(state as f32));
+
+    let size = [spawn.width as f32, spawn.height as f32];
+    let center = [size[0] * 0.5, size[1] * 0.5];
+    let [extent_x, extent_y] = spawn.extent;
+
+    let mut position = center;
+    let mut angle = draws[2] * 2.0 * PI;
+
+    match spawn.mode {
+        // Random
+        1 => position = [draws[0] * size[0], draws[1] * size[1]],
+        // Circle, with sqrt keeping the density uniform over the disc.
+        2 => {
+            let distance = extent_x * draws[0].sqrt();
+            let theta = draws[1] * 2.0 * PI;
+
+            position = [
+                center[0] + distance * theta.cos(),
+                center[1] + distance * theta.sin(),
+            ];
+            angle = theta + PI;
+        }
+        // Ring
+        3 => {
+            let theta = draws[0] * 2.0 * PI;
+
+            position = [
+                center[0] + extent_x * theta.cos(),
+                center[1] + extent_x * theta.sin(),
+            ];
+            angle = if spawn.inward != 0 { theta + PI } else { theta };
+        }
+        // Rect
+        4 => {
+            position = [
+                center[0] + (draws[0] - 0.5) * extent_x,
+                center[1] + (draws[1] - 0.5) * extent_y,
+            ];
+        }
+        // Point
+        _ => {}
+    }
+
+    Agent {
+        position: [
+            position[0].clamp(0.0, size[0] - 1.0),
+            position[1].clamp(0.0, size[1] - 1.0),
+        ],
+        angle,
+        species_index: index % spawn.num_species,
+    }
+}
+
+fn hash(state: u32) -> u32 {
+    let state1 = state ^ 2747636419;
+    let state2 = state1.wrapping_mul(2654435769);
+    let state3 = state2 ^ state >> 16;
+    let state4 = state3.wrapping_mul(2654435769);
+    let state5 = state4 ^ state >> 16;
+
+    state5.wrapping_mul(2654435769)
+}
+
+fn scale_to_range(state: f32) -> f32 {
+    state / 4294967295.0
+}
+
+/// One-hot mask selecting the trail channel owned by a species.
+fn species_mask(species_index: u32) -> [f32; 4] {
+    std::array::from_fn(|channel| (channel as u32 == species_index) as u32 as f32)
+}
+
+fn mix(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    std::array::from_fn(|channel| a[channel] * (1.0 - t) + b[channel] * t)
+}
+
+fn round_to_half(texel: [f32; 4]) -> [f32; 4] {
+    texel.map(|value| f16::from_f32(value).to_f32())
+}
+
+/// Linear to sRGB, as an sRGB surface stores it.
+fn encode_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::rc::Rc;
+
+    use clap::ValueEnum;
+
+    use super::*;
+    use crate::{
+        agent_buffers::AgentBuffers,
+        app, headless,
+        pipeline::{Pipeline, SpawnPipeline, SpawnSetup, Viewport},
+        settings::{Facing, FoodShape, FoodSource, SpawnMode},
+        shader_pipeline::ShaderPipeline,
+        simulation::Simulation,
+    };
+
+    fn seeded_settings(seed: u64) -> AppSettings {
+        AppSettings {
+            width: 64,
+            height: 48,
+            num_agents: 2_000,
+            seed: Some(seed),
+            spawn: SpawnMode::Random,
+            ..AppSettings::default()
+        }
+    }
+
+    fn run(settings: AppSettings, steps: usize) -> CpuSimulation {
+        let mut simulation = CpuSimulation::from_snapshot(&spawn::initial_snapshot(settings));
+
+        for _ in 0..steps {
+            simulation.step();
+        }
+
+        simulation
+    }
+
+    fn trail_bits(simulation: &CpuSimulation) -> Vec<u32> {
+        simulation
+            .trail_map
+            .iter()
+            .flatten()
+            .map(|value| value.to_bits())
+            .collect()
+    }
+
+    fn agent_bytes(simulation: &CpuSimulation) -> &[u8] {
+        bytemuck::cast_slice(&simulation.agents)
+    }
+
+    #[test]
+    fn same_seed_repeats_bit_for_bit() {
+        let first = run(seeded_settings(42), 30);
+        let second = run(seeded_settings(42), 30);
+
+        assert_eq!(agent_bytes(&first), agent_bytes(&second));
+        assert_eq!(trail_bits(&first), trail_bits(&second));
+    }
+
+    #[test]
+    fn different_seeds_differ() {
+        let first = run(seeded_settings(42), 30);
+        let second = run(seeded_settings(43), 30);
+
+        assert_ne!(agent_bytes(&first), agent_bytes(&second));
+        assert_ne!(trail_bits(&first), trail_bits(&second));
+    }
+
+    /// A map of `width` by `height` with no agents, `hot` texels set in
+    /// every channel and walls at `walls`.
+    fn grid(
+        size: [u32; 2],
+        hot: &[([u32; 2], f32)],
+        walls: &[[u32; 2]],
+        settings: AppSettings,
+    ) -> CpuSimulation {
+        let [width, height] = size;
+        let texel = |[x, y]: [u32; 2]| (y * width + x) as usize;
+
+        let mut trail_map = vec![[f16::ZERO; 4]; (width * height) as usize];
+        for &(position, value) in hot {
+            trail_map[texel(position)] = [f16::from_f32(value); 4];
+        }
+
+        let mut obstacles = vec![0; (width * height) as usize];
+        for &position in walls {
+            obstacles[texel(position)] = obstacles::WALL;
+        }
+
+        CpuSimulation::from_snapshot(&Snapshot {
+            settings: AppSettings {
+                width,
+                height,
+                num_agents: 0,
+                ..settings
+            },
+            seed: 0,
+            step: 0,
+            agents: Vec::new(),
+            trail_map,
+            obstacles,
+        })
+    }
+
+    /// Blurs fully into the 3x3 average, without decay.
+    fn blur_only(boundary: BoundaryMode) -> AppSettings {
+        AppSettings {
+            boundary,
+            diffuse_rate: 1.0 / STEP_DELTA_TIME,
+            decay_rate: 0.0,
+            ..AppSettings::default()
+        }
+    }
+
+    /// The first channel of the trail map, row by row.
+    fn rows(simulation: &CpuSimulation) -> Vec<Vec<f32>> {
+        simulation
+            .trail_map
+            .chunks(simulation.settings.width as usize)
+            .map(|row| row.iter().map(|texel| texel[0]).collect())
+            .collect()
+    }
+
+    // 0.5625 spread over nine texels leaves 0.0625 on each, both exact in
+    // half precision.
+    const HOT: f32 = 0.5625;
+    const NINTH: f32 = 0.0625;
+
+    #[test]
+    fn diffuse_averages_neighbours() {
+        let mut simulation = grid(
+            [5, 5],
+            &[([2, 2], HOT)],
+            &[],
+            blur_only(BoundaryMode::Clamp),
+        );
+        simulation.diffuse(&BrushBuffer::default());
+
+        assert_eq!(
+            rows(&simulation),
+            [
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [0.0, NINTH, NINTH, NINTH, 0.0],
+                [0.0, NINTH, NINTH, NINTH, 0.0],
+                [0.0, NINTH, NINTH, NINTH, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn diffuse_mixes_by_rate_and_decays() {
+        let settings = AppSettings {
+            diffuse_rate: 0.5 / STEP_DELTA_TIME,
+            decay_rate: 0.125 / STEP_DELTA_TIME,
+            ..AppSettings::default()
+        };
+        let mut simulation = grid([5, 5], &[([2, 2], HOT)], &[], settings);
+        simulation.diffuse(&BrushBuffer::default());
+
+        // Half of 0.5625 plus half of its average, less 0.125. The texels
+        // around it only get 0.03125 before the decay takes it all.
+        let middle = (HOT + NINTH) / 2.0 - 0.125;
+
+        assert_eq!(
+            rows(&simulation),
+            [
+                [0.0; 5],
+                [0.0; 5],
+                [0.0, 0.0, middle, 0.0, 0.0],
+                [0.0; 5],
+                [0.0; 5],
+            ]
+        );
+    }
+
+    #[test]
+    fn diffuse_skips_walls() {
+        let mut simulation = grid(
+            [5, 5],
+            &[([2, 2], HOT), ([3, 2], HOT)],
+            &[[3, 2]],
+            blur_only(BoundaryMode::Clamp),
+        );
+        simulation.diffuse(&BrushBuffer::default());
+
+        // Trail on the wall is cleared and not blurred out, and texels next
+        // to the wall average their eight open neighbours.
+        let eighth = HOT / 8.0;
+
+        assert_eq!(
+            rows(&simulation),
+            [
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [0.0, NINTH, eighth, eighth, 0.0],
+                [0.0, NINTH, eighth, 0.0, 0.0],
+                [0.0, NINTH, eighth, eighth, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+            ]
+        );
+    }
+
+    #[test]
+    fn diffuse_wraps_on_a_torus() {
+        let mut simulation = grid(
+            [5, 5],
+            &[([0, 0], HOT)],
+            &[],
+            blur_only(BoundaryMode::Torus),
+        );
+        simulation.diffuse(&BrushBuffer::default());
+
+        assert_eq!(
+            rows(&simulation),
+            [
+                [NINTH, NINTH, 0.0, 0.0, NINTH],
+                [NINTH, NINTH, 0.0, 0.0, NINTH],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [NINTH, NINTH, 0.0, 0.0, NINTH],
+            ]
+        );
+    }
+
+    #[test]
+    fn diffuse_clamps_at_the_far_edge() {
+        let mut simulation = grid(
+            [5, 5],
+            &[([4, 4], HOT)],
+            &[],
+            blur_only(BoundaryMode::Clamp),
+        );
+        simulation.diffuse(&BrushBuffer::default());
+
+        // Past the far edge the last texel is sampled again. Below zero the
+        // unsigned coordinate wraps before the clamp, so column and row zero
+        // also see the far edge, as on the GPU.
+        let two = 2.0 * NINTH;
+        let four = 4.0 * NINTH;
+
+        assert_eq!(
+            rows(&simulation),
+            [
+                [NINTH, 0.0, 0.0, NINTH, two],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, 0.0],
+                [NINTH, 0.0, 0.0, NINTH, two],
+                [two, 0.0, 0.0, two, four],
+            ]
+        );
+    }
+
+    fn spawn_modes() -> [SpawnMode; 6] {
+        [
+            SpawnMode::Point,
+            SpawnMode::Random,
+            SpawnMode::Circle { radius: 20.0 },
+            SpawnMode::Ring {
+                radius: 15.0,
+                facing: Facing::Inward,
+            },
+            SpawnMode::Ring {
+                radius: 15.0,
+                facing: Facing::Outward,
+            },
+            SpawnMode::Rect {
+                width: 30.0,
+                height: 10.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn spawned_agents_depend_only_on_their_index() {
+        let settings = AppSettings {
+            width: 64,
+            height: 48,
+            spawn: SpawnMode::Random,
+            species: vec![SpeciesSettings::default(); 3],
+            ..AppSettings::default()
+        };
+
+        let all = spawned_agents(&SpawnBuffer::new(&settings, 5, 0, 0, 110));
+        let tail = spawned_agents(&SpawnBuffer::new(&settings, 5, 0, 100, 10));
+
+        assert_eq!(
+            bytemuck::cast_slice::<Agent, u8>(&all[100..]),
+            bytemuck::cast_slice::<Agent, u8>(&tail)
+        );
+
+        for (index, agent) in all.iter().enumerate() {
+            assert_eq!(agent.species_index, index as u32 % 3);
+            assert!((0.0..=63.0).contains(&agent.position[0]));
+            assert!((0.0..=47.0).contains(&agent.position[1]));
+        }
+
+        let later = spawned_agents(&SpawnBuffer::new(&settings, 5, 1, 100, 10));
+        assert_ne!(
+            bytemuck::cast_slice::<Agent, u8>(&later),
+            bytemuck::cast_slice::<Agent, u8>(&tail)
+        );
+    }
+
+    /// A device that can run the compute shaders, or `None` to skip a test
+    /// where there is no such adapter, not even a software one.
+    pub(crate) fn compute_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        match pollster::block_on(headless::request_device()) {
+            Some((adapter, device, queue)) if app::supports_compute(&adapter) => {
+                Some((device, queue))
+            }
+            _ => {
+                eprintln!("No adapter with compute shaders, skipping");
+                None
+            }
+        }
+    }
+
+    /// A render target for pipelines that want a frame to draw to.
+    pub(crate) fn frame(device: &wgpu::Device, size: [u32; 2]) -> wgpu::TextureView {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("slime::test::frame"),
+                size: wgpu::Extent3d {
+                    width: size[0],
+                    height: size[1],
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    #[test]
+    fn spawned_agents_match_the_shader() {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        let (first_index, count) = (300, 1_000);
+
+        for spawn in spawn_modes() {
+            let settings = AppSettings {
+                width: 64,
+                height: 48,
+                spawn,
+                species: vec![SpeciesSettings::default(); 2],
+                ..AppSettings::default()
+            };
+
+            let agents = Rc::new(AgentBuffers::new(&device, &queue, first_index + count, &[]));
+            let mut pipeline = SpawnPipeline::new(
+                &device,
+                &settings,
+                &SpawnSetup {
+                    agents: agents.clone(),
+                },
+            );
+
+            let buffer = SpawnBuffer::new(&settings, 1234, 56, first_index, count);
+            pipeline.update(&queue, &buffer);
+
+            let mut encoder =
+                device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            pipeline.execute(&mut encoder, &frame(&device, [1, 1]));
+            queue.submit(Some(encoder.finish()));
+
+            let gpu = agents.read(&device, &queue, first_index + count);
+            let cpu = spawned_agents(&buffer);
+
+            // Slots before the first index are left alone.
+            assert!(gpu[..first_index]
+                .iter()
+                .all(|agent| bytemuck::bytes_of(agent).iter().all(|&byte| byte == 0)));
+
+            for (gpu, cpu) in gpu[first_index..].iter().zip(&cpu) {
+                assert_eq!(gpu.species_index, cpu.species_index, "{:?}", spawn);
+                assert!((gpu.angle - cpu.angle).abs() < 1e-4, "{:?}", spawn);
+
+                for axis in 0..2 {
+                    let difference = (gpu.position[axis] - cpu.position[axis]).abs();
+                    assert!(difference < 1e-3, "{:?} off by {}", spawn, difference);
+                }
+            }
+        }
+    }
+
+    /// Runs `snapshot` for a few steps with `stroke` held on both backends
+    /// and compares the trail maps.
+    fn compare_steps(snapshot: &Snapshot, stroke: Option<Stroke>) {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        const STEPS: usize = 5;
+
+        let mut cpu = CpuSimulation::from_snapshot(snapshot);
+        cpu.set_brush(stroke);
+        for _ in 0..STEPS {
+            cpu.step();
+        }
+
+        let viewport = Viewport {
+            width: snapshot.settings.width,
+            height: snapshot.settings.height,
+        };
+        let mut gpu = ShaderPipeline::from_snapshot(
+            snapshot,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            viewport,
+            &device,
+            &queue,
+        );
+        gpu.set_brush(stroke);
+        gpu.step(STEPS);
+        gpu.render(
+            &frame(&device, [viewport.width, viewport.height]),
+            &device,
+            &queue,
+            0.0,
+        );
+
+        let gpu = gpu.snapshot(&device, &queue);
+        assert_eq!(gpu.step, cpu.step);
+
+        // Floating-point differences nudge the odd agent onto a neighbouring
+        // texel, so single texels may differ while the map as a whole agrees.
+        let mut total = 0.0;
+        let mut largest = 0.0f32;
+
+        for (gpu, cpu) in gpu.trail_map.iter().zip(&cpu.trail_map) {
+            for (gpu, cpu) in gpu.iter().zip(cpu) {
+                let difference = (gpu.to_f32() - cpu).abs();
+                total += difference;
+                largest = largest.max(difference);
+            }
+        }
+
+        let mean = total / (cpu.trail_map.len() * 4) as f32;
+
+        assert!(mean < 2e-4, "mean difference {}", mean);
+        assert!(largest < 0.05, "largest difference {}", largest);
+    }
+
+    pub(crate) fn parity_settings() -> AppSettings {
+        AppSettings {
+            width: 96,
+            height: 64,
+            num_agents: 5_000,
+            seed: Some(7),
+            spawn: SpawnMode::Circle { radius: 25.0 },
+            max_steps_per_frame: 5,
+            ..AppSettings::default()
+        }
+    }
+
+    #[test]
+    fn steps_match_the_shaders() {
+        let mut snapshot = spawn::initial_snapshot(parity_settings());
+
+        // A wall across part of the circle.
+        let width = snapshot.settings.width as usize;
+        for y in 20..44 {
+            for x in 50..54 {
+                snapshot.obstacles[y * width + x] = obstacles::WALL;
+            }
+        }
+
+        compare_steps(&snapshot, None);
+    }
+
+    #[test]
+    fn edges_match_the_shaders() {
+        // Spread over the whole map, so agents cross every edge.
+        for &boundary in BoundaryMode::value_variants() {
+            let settings = AppSettings {
+                spawn: SpawnMode::Random,
+                boundary,
+                ..parity_settings()
+            };
+
+            compare_steps(&spawn::initial_snapshot(settings), None);
+        }
+    }
+
+    #[test]
+    fn food_matches_the_shaders() {
+        let settings = AppSettings {
+            food: vec![FoodSource {
+                shape: FoodShape::Circle {
+                    position: [-30.0, 10.0],
+                    radius: 6.0,
+                },
+                strength: 20.0,
+                species: None,
+            }],
+            ..parity_settings()
+        };
+
+        compare_steps(&spawn::initial_snapshot(settings), None);
+    }
+
+    #[test]
+    fn paint_matches_the_shaders() {
+        let stroke = Stroke {
+            position: [40.0, 30.0],
+            tool: Tool::Paint,
+        };
+
+        compare_steps(&spawn::initial_snapshot(parity_settings()), Some(stroke));
+    }
+
+    pub(crate) fn delete_stroke() -> Option<Stroke> {
+        Some(Stroke {
+            position: [48.0, 32.0],
+            tool: Tool::Delete,
+        })
+    }
+
+    pub(crate) fn live_agents(agents: &[Agent]) -> usize {
+        agents
+            .iter()
+            .filter(|agent| agents::is_alive(agent))
+            .count()
+    }
+
+    #[test]
+    fn deleting_lowers_the_live_count() {
+        let mut simulation = CpuSimulation::new(parity_settings());
+        simulation.set_brush(delete_stroke());
+        for _ in 0..3 {
+            simulation.step();
+        }
+
+        let live = simulation.settings().num_agents;
+        assert!(live < 5_000);
+        assert_eq!(live, live_agents(&simulation.agents));
+
+        // Snapshots keep the dead slots, and count them, so a resumed run
+        // lays its agents out the same.
+        let snapshot = simulation.snapshot();
+        assert_eq!(snapshot.settings.num_agents, 5_000);
+        assert_eq!(
+            CpuSimulation::from_snapshot(&snapshot)
+                .settings()
+                .num_agents,
+            live
+        );
+    }
+}
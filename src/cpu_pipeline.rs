@@ -0,0 +1,253 @@
+use half::f16;
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    brush::Stroke,
+    capture,
+    clock::StepClock,
+    cpu::CpuSimulation,
+    obstacles,
+    pipeline::{Pipeline, RenderPipeline, RenderSettings, Viewport},
+    settings::AppSettings,
+    simulation::Simulation,
+    snapshot::Snapshot,
+};
+
+/// Runs the simulation on the CPU and uploads the display each frame, so it
+/// presents like `ShaderPipeline` on adapters without compute shaders.
+pub struct CpuPipeline {
+    simulation: CpuSimulation,
+    clock: StepClock,
+    display_texture: wgpu::Texture,
+    obstacle_map: wgpu::Texture,
+    render_pipeline: RenderPipeline,
+    format: wgpu::TextureFormat,
+    viewport: Viewport,
+}
+
+impl CpuPipeline {
+    pub fn new(
+        settings: AppSettings,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        Self::with_simulation(
+            CpuSimulation::new(settings),
+            format,
+            viewport,
+            device,
+            queue,
+        )
+    }
+
+    pub fn from_snapshot(
+        snapshot: &Snapshot,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        Self::with_simulation(
+            CpuSimulation::from_snapshot(snapshot),
+            format,
+            viewport,
+            device,
+            queue,
+        )
+    }
+
+    fn with_simulation(
+        simulation: CpuSimulation,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let (display_texture, obstacle_map, render_pipeline) =
+            create_display(&simulation, format, viewport, device, queue);
+
+        Self {
+            simulation,
+            clock: StepClock::default(),
+            display_texture,
+            obstacle_map,
+            render_pipeline,
+            format,
+            viewport,
+        }
+    }
+}
+
+/// The texture the simulation is uploaded to each frame, the walls drawn
+/// over it, and the pipeline that draws them.
+fn create_display(
+    simulation: &CpuSimulation,
+    format: wgpu::TextureFormat,
+    viewport: Viewport,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> (wgpu::Texture, wgpu::Texture, RenderPipeline) {
+    let settings = simulation.settings();
+    let size = PhysicalSize::new(settings.width, settings.height);
+
+    let display_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("slime::cpu::display_texture"),
+        size: wgpu::Extent3d {
+            width: settings.width,
+            height: settings.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let obstacle_map = obstacles::create_texture(device, &size);
+    obstacles::upload(queue, &obstacle_map, simulation.obstacles());
+
+    let render_setup = RenderSettings {
+        format,
+        texture_view: display_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        obstacle_view: obstacle_map.create_view(&wgpu::TextureViewDescriptor::default()),
+        width: settings.width,
+        height: settings.height,
+        viewport,
+    };
+
+    let render_pipeline = RenderPipeline::new(device, settings, &render_setup);
+
+    (display_texture, obstacle_map, render_pipeline)
+}
+
+impl Simulation for CpuPipeline {
+    fn settings(&self) -> &AppSettings {
+        self.simulation.settings()
+    }
+
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &AppSettings) {
+        self.simulation.set_settings(settings);
+        self.render_pipeline
+            .set_settings(queue, self.simulation.settings());
+    }
+
+    fn resize(&mut self, queue: &wgpu::Queue, viewport: Viewport) {
+        self.viewport = viewport;
+        self.render_pipeline.update(queue, &viewport);
+    }
+
+    fn resize_simulation(
+        &mut self,
+        size: &PhysicalSize<u32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let settings = self.simulation.settings();
+        let old_size = PhysicalSize::new(settings.width, settings.height);
+
+        if size.width == 0 || size.height == 0 || *size == old_size {
+            return;
+        }
+
+        self.simulation.resize(size);
+
+        log::info!(
+            "Resized simulation from {}x{} to {}x{}",
+            old_size.width,
+            old_size.height,
+            size.width,
+            size.height
+        );
+
+        (
+            self.display_texture,
+            self.obstacle_map,
+            self.render_pipeline,
+        ) = create_display(&self.simulation, self.format, self.viewport, device, queue);
+    }
+
+    fn set_agent_count(&mut self, count: usize, _device: &wgpu::Device, _queue: &wgpu::Queue) {
+        self.simulation.set_agent_count(count);
+    }
+
+    fn set_brush(&mut self, stroke: Option<Stroke>) {
+        self.simulation.set_brush(stroke);
+    }
+
+    fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    fn step_count(&self) -> u32 {
+        self.simulation.step_count()
+    }
+
+    fn toggle_pause(&mut self) {
+        self.clock.toggle_pause();
+    }
+
+    fn step(&mut self, count: usize) {
+        self.clock.step(count);
+    }
+
+    fn swap_buffers(&mut self) {
+        let mut settings = self.simulation.settings().clone();
+        settings.agents_only = !settings.agents_only;
+
+        self.simulation.set_settings(&settings);
+    }
+
+    fn screenshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<image::RgbaImage> {
+        capture::screenshot(
+            device,
+            queue,
+            &self.render_pipeline,
+            self.format,
+            self.viewport,
+        )
+    }
+
+    fn trail_map_image(&self, _device: &wgpu::Device, _queue: &wgpu::Queue) -> image::Rgba32FImage {
+        self.simulation.trail_map_image()
+    }
+
+    fn snapshot(&self, _device: &wgpu::Device, _queue: &wgpu::Queue) -> Snapshot {
+        self.simulation.snapshot()
+    }
+
+    fn render(
+        &mut self,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) {
+        for _ in 0..self.clock.advance(self.simulation.settings(), elapsed) {
+            self.simulation.step();
+        }
+
+        let size = self.display_texture.size();
+
+        queue.write_texture(
+            self.display_texture.as_image_copy(),
+            bytemuck::cast_slice(&self.simulation.display()),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(
+                    size.width * std::mem::size_of::<[f16; 4]>() as u32,
+                ),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
+            },
+            size,
+        );
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.render_pipeline.execute(&mut command_encoder, frame);
+        queue.submit(Some(command_encoder.finish()));
+    }
+}
@@ -0,0 +1,69 @@
+//! Food sources, rasterized into a map of how much attractant each texel of
+//! the trail map gains per second of simulated time.
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    settings::{AppSettings, FoodShape},
+    spawn::ImageSource,
+};
+
+/// The food map for `settings.food` at `size`, one value per trail channel.
+/// Overlapping sources add up. Images that fail to load are skipped.
+pub fn food_map(settings: &AppSettings, size: &PhysicalSize<u32>) -> Vec<[f32; 4]> {
+    let width = size.width as usize;
+    let center = [size.width as f32 / 2.0, size.height as f32 / 2.0];
+
+    let mut map = vec![[0.0; 4]; width * size.height as usize];
+
+    for food in &settings.food {
+        let channels = match food.species {
+            Some(species) => species..species + 1,
+            None => 0..settings.species.len(),
+        };
+
+        let mut add = |index: usize, amount: f32| {
+            for channel in channels.clone() {
+                map[index][channel] += amount * food.strength;
+            }
+        };
+
+        match &food.shape {
+            FoodShape::Point { position } => {
+                let x = (center[0] + position[0]).floor();
+                let y = (center[1] + position[1]).floor();
+
+                if (0.0..size.width as f32).contains(&x) && (0.0..size.height as f32).contains(&y) {
+                    add(y as usize * width + x as usize, 1.0);
+                }
+            }
+            FoodShape::Circle { position, radius } => {
+                let x = center[0] + position[0];
+                let y = center[1] + position[1];
+
+                for row in 0..size.height {
+                    for column in 0..size.width {
+                        let dx = column as f32 + 0.5 - x;
+                        let dy = row as f32 + 0.5 - y;
+
+                        if dx * dx + dy * dy <= radius * radius {
+                            add(row as usize * width + column as usize, 1.0);
+                        }
+                    }
+                }
+            }
+            FoodShape::Image { path } => match ImageSource::load(path, size) {
+                Ok(image) => {
+                    for (index, brightness) in image.luminance().enumerate() {
+                        add(index, brightness);
+                    }
+                }
+                Err(err) => {
+                    log::error!("Could not load food image {}: {}", path.display(), err)
+                }
+            },
+        }
+    }
+
+    map
+}
@@ -0,0 +1,631 @@
+use clap::ValueEnum;
+use instant::Instant;
+use winit::{
+    event::{self, WindowEvent},
+    window::Window,
+};
+
+use crate::{
+    cli::SpawnKind,
+    pipeline::{GuiPipeline, ScreenDescriptor},
+    settings::{
+        AppSettings, BoundaryMode, Fit, SpawnHeading, SpawnMode, SpeciesSettings, MAX_AGENTS,
+        MAX_SENSOR_SIZE, MAX_SPECIES,
+    },
+};
+
+/// Key that shows or hides the parameter panel.
+const TOGGLE_KEY: event::VirtualKeyCode = event::VirtualKeyCode::F1;
+
+/// Immediate-mode parameter panel drawn over the simulation.
+pub struct Gui {
+    context: egui::Context,
+    painter: GuiPipeline,
+    raw_input: egui::RawInput,
+    pointer_pos: egui::Pos2,
+    pixels_per_point: f32,
+    start_time: Instant,
+    visible: bool,
+    restart: Restart,
+    /// The agent count being edited, applied once the edit is finished.
+    agent_count: Option<usize>,
+}
+
+/// Values shown in the panel that are owned by the event loop.
+pub struct PanelStatus {
+    pub fps: f32,
+    pub paused: bool,
+    pub step: u32,
+}
+
+/// What the user asked for through the panel this frame.
+#[derive(Default)]
+pub struct PanelResponse {
+    pub toggle_pause: bool,
+    /// Steps to advance by, pausing first.
+    pub step: usize,
+    pub restart: bool,
+    /// Agent count to grow or shrink the running population to.
+    pub num_agents: Option<usize>,
+}
+
+/// Settings that only take effect when the simulation is rebuilt. They
+/// follow the running settings until they are edited with the Restart
+/// section open.
+struct Restart {
+    width: u32,
+    height: u32,
+    num_agents: usize,
+    num_species: usize,
+    spawn: SpawnMode,
+    /// The Restart section was open on the last frame.
+    open: bool,
+    /// A value was changed since the last restart.
+    edited: bool,
+}
+
+impl Restart {
+    fn new(settings: &AppSettings) -> Self {
+        Self {
+            width: settings.width,
+            height: settings.height,
+            num_agents: settings.num_agents,
+            num_species: settings.species.len(),
+            spawn: settings.spawn,
+            open: false,
+            edited: false,
+        }
+    }
+
+    /// Picks up the running `settings` unless the values are being edited.
+    fn refresh(&mut self, settings: &AppSettings) {
+        if !self.open || !self.edited {
+            *self = Self {
+                open: self.open,
+                ..Self::new(settings)
+            };
+        }
+    }
+
+    /// Writes the values into `settings` for a restart.
+    fn apply(&mut self, settings: &mut AppSettings) {
+        settings.width = self.width;
+        settings.height = self.height;
+        settings.num_agents = self.num_agents;
+        settings.resize_species(self.num_species);
+        settings.spawn = self.spawn;
+
+        self.edited = false;
+    }
+}
+
+impl Gui {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        window: &Window,
+        settings: &AppSettings,
+    ) -> Self {
+        Self {
+            context: egui::Context::default(),
+            painter: GuiPipeline::new(device, format),
+            raw_input: egui::RawInput::default(),
+            pointer_pos: egui::Pos2::ZERO,
+            pixels_per_point: window.scale_factor() as f32,
+            start_time: Instant::now(),
+            visible: true,
+            restart: Restart::new(settings),
+            agent_count: None,
+        }
+    }
+
+    /// Feeds a window event to the panel. Returns `true` when the panel
+    /// consumed it and the simulation should not react to it.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::KeyboardInput {
+            input:
+                event::KeyboardInput {
+                    virtual_keycode: Some(TOGGLE_KEY),
+                    state: event::ElementState::Pressed,
+                    ..
+                },
+            ..
+        } = event
+        {
+            self.visible = !self.visible;
+            return true;
+        }
+
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            self.pixels_per_point = *scale_factor as f32;
+        }
+
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_pos = egui::pos2(
+                    position.x as f32 / self.pixels_per_point,
+                    position.y as f32 / self.pixels_per_point,
+                );
+                self.raw_input
+                    .events
+                    .push(egui::Event::PointerMoved(self.pointer_pos));
+                self.wants_pointer()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.raw_input.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    event::MouseButton::Left => egui::PointerButton::Primary,
+                    event::MouseButton::Right => egui::PointerButton::Secondary,
+                    event::MouseButton::Middle => egui::PointerButton::Middle,
+                    event::MouseButton::Other(_) => return false,
+                };
+
+                self.raw_input.events.push(egui::Event::PointerButton {
+                    pos: self.pointer_pos,
+                    button,
+                    pressed: *state == event::ElementState::Pressed,
+                    modifiers: self.raw_input.modifiers,
+                });
+                self.wants_pointer()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    event::MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * 50.0,
+                    event::MouseScrollDelta::PixelDelta(delta) => egui::vec2(
+                        delta.x as f32 / self.pixels_per_point,
+                        delta.y as f32 / self.pixels_per_point,
+                    ),
+                };
+
+                self.raw_input.events.push(egui::Event::Scroll(delta));
+                self.wants_pointer()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.raw_input.modifiers = egui::Modifiers {
+                    alt: state.alt(),
+                    ctrl: state.ctrl(),
+                    shift: state.shift(),
+                    mac_cmd: cfg!(target_os = "macos") && state.logo(),
+                    command: if cfg!(target_os = "macos") {
+                        state.logo()
+                    } else {
+                        state.ctrl()
+                    },
+                };
+                false
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                if character.is_control() {
+                    return self.context.wants_keyboard_input();
+                }
+
+                self.raw_input
+                    .events
+                    .push(egui::Event::Text(character.to_string()));
+                self.context.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                if let Some(key) = input.virtual_keycode.and_then(translate_key) {
+                    self.raw_input.events.push(egui::Event::Key {
+                        key,
+                        pressed: input.state == event::ElementState::Pressed,
+                        repeat: false,
+                        modifiers: self.raw_input.modifiers,
+                    });
+                }
+
+                self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the panel and paints it on top of `frame`. `settings` holds the
+    /// live settings and is edited in place.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: &wgpu::TextureView,
+        window: &Window,
+        status: &PanelStatus,
+        settings: &mut AppSettings,
+    ) -> PanelResponse {
+        let mut response = PanelResponse::default();
+
+        if !self.visible {
+            return response;
+        }
+
+        let size = window.inner_size();
+        let mut raw_input = std::mem::take(&mut self.raw_input);
+        self.raw_input.modifiers = raw_input.modifiers;
+
+        raw_input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(
+                size.width as f32 / self.pixels_per_point,
+                size.height as f32 / self.pixels_per_point,
+            ),
+        ));
+        raw_input.pixels_per_point = Some(self.pixels_per_point);
+        raw_input.time = Some(self.start_time.elapsed().as_secs_f64());
+
+        self.restart.refresh(settings);
+
+        let restart = &mut self.restart;
+        let agent_count = &mut self.agent_count;
+        let output = self.context.run(raw_input, |context| {
+            panel(
+                context,
+                settings,
+                restart,
+                agent_count,
+                status,
+                &mut response,
+            );
+        });
+
+        if response.restart {
+            self.restart.apply(settings);
+        }
+
+        let primitives = self.context.tessellate(output.shapes);
+        let screen = ScreenDescriptor {
+            width: size.width,
+            height: size.height,
+            pixels_per_point: self.pixels_per_point,
+        };
+
+        self.painter
+            .update(device, queue, &output.textures_delta, &primitives, &screen);
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.painter.execute(&mut command_encoder, frame);
+        queue.submit(Some(command_encoder.finish()));
+
+        response
+    }
+
+    /// Drops unapplied edits to the Restart section, for a run started or
+    /// loaded some other way.
+    pub fn reset_restart(&mut self) {
+        self.restart.edited = false;
+    }
+
+    fn wants_pointer(&self) -> bool {
+        self.context.wants_pointer_input() || self.context.is_pointer_over_area()
+    }
+}
+
+fn panel(
+    context: &egui::Context,
+    settings: &mut AppSettings,
+    restart: &mut Restart,
+    agent_count: &mut Option<usize>,
+    status: &PanelStatus,
+    response: &mut PanelResponse,
+) {
+    egui::Window::new("Slime")
+        .default_pos([10.0, 10.0])
+        .resizable(false)
+        .show(context, |ui| {
+            ui.label(format!("{:.0} fps", status.fps));
+            ui.horizontal(|ui| {
+                let mut count = agent_count.unwrap_or(settings.num_agents);
+                let drag = ui.add(
+                    egui::DragValue::new(&mut count)
+                        .speed(1000.0)
+                        .clamp_range(1..=MAX_AGENTS),
+                );
+                ui.label("agents");
+
+                if drag.changed() {
+                    *agent_count = Some(count);
+                }
+
+                // Every change rebuilds the agent buffer, so it waits for the
+                // drag or the typing to end.
+                if drag.drag_released() || drag.lost_focus() {
+                    response.num_agents = agent_count.take();
+                }
+            });
+            ui.label(format!("step {}", status.step));
+
+            ui.horizontal(|ui| {
+                let pause_label = if status.paused { "Resume" } else { "Pause" };
+                response.toggle_pause = ui.button(pause_label).clicked();
+
+                if ui.button("Step").clicked() {
+                    response.step = 1;
+                }
+
+                if ui
+                    .button(format!("Step {}", settings.frame_advance))
+                    .clicked()
+                {
+                    response.step = settings.frame_advance;
+                }
+
+                ui.add(egui::DragValue::new(&mut settings.frame_advance).clamp_range(1..=10_000));
+            });
+
+            for (index, species) in settings.species.iter_mut().enumerate() {
+                egui::CollapsingHeader::new(format!("Species {}", index + 1))
+                    .default_open(index == 0)
+                    .show(ui, |ui| species_panel(ui, species));
+            }
+
+            egui::CollapsingHeader::new("Trails")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut settings.trail_weight, 0.0..=20.0)
+                            .text("trail weight"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.decay_rate, 0.0..=5.0).text("decay rate"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.diffuse_rate, 0.0..=50.0)
+                            .text("diffuse rate"),
+                    );
+                });
+
+            egui::CollapsingHeader::new("Simulation")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut settings.sim_rate, 1.0..=1000.0)
+                            .logarithmic(true)
+                            .text("steps per second"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.time_scale, 0.0..=16.0)
+                            .logarithmic(true)
+                            .text("time scale"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.max_steps_per_frame, 1..=64)
+                            .text("max steps per frame"),
+                    );
+                    ui.checkbox(&mut settings.agents_only, "agents only");
+                    boundary_panel(ui, &mut settings.boundary);
+                    fit_panel(ui, &mut settings.fit);
+                    ui.checkbox(&mut settings.resize_with_window, "resize with window");
+                });
+
+            egui::CollapsingHeader::new("Brush")
+                .default_open(false)
+                .show(ui, |ui| {
+                    ui.add(
+                        egui::Slider::new(&mut settings.brush.radius, 1.0..=200.0)
+                            .logarithmic(true)
+                            .text("radius"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.brush.strength, 0.0..=10.0)
+                            .text("strength"),
+                    );
+                    ui.add(
+                        egui::Slider::new(&mut settings.brush.spawn_count, 1..=10_000)
+                            .logarithmic(true)
+                            .text("agents spawned per step"),
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("spawn heading");
+                        ui.radio_value(
+                            &mut settings.brush.spawn_heading,
+                            SpawnHeading::Random,
+                            "random",
+                        );
+                        ui.radio_value(
+                            &mut settings.brush.spawn_heading,
+                            SpawnHeading::Outward,
+                            "outward",
+                        );
+                    });
+                    ui.label("Drag to paint, right-drag to erase, hold Shift to attract or repel");
+                    ui.label("Hold Ctrl to spawn or delete agents");
+                });
+
+            let section = egui::CollapsingHeader::new("Restart")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let mut changed = ui
+                        .horizontal(|ui| {
+                            ui.label("width");
+                            let width = ui.add(
+                                egui::DragValue::new(&mut restart.width).clamp_range(1..=8192),
+                            );
+                            ui.label("height");
+                            let height = ui.add(
+                                egui::DragValue::new(&mut restart.height).clamp_range(1..=8192),
+                            );
+
+                            width.changed() || height.changed()
+                        })
+                        .inner;
+                    changed |= ui
+                        .horizontal(|ui| {
+                            ui.label("agents");
+                            let agents = ui.add(
+                                egui::DragValue::new(&mut restart.num_agents)
+                                    .speed(1000.0)
+                                    .clamp_range(1..=MAX_AGENTS),
+                            );
+                            ui.label("species");
+                            let species = ui.add(
+                                egui::DragValue::new(&mut restart.num_species)
+                                    .clamp_range(1..=MAX_SPECIES),
+                            );
+
+                            agents.changed() || species.changed()
+                        })
+                        .inner;
+
+                    let spawn = restart.spawn;
+                    spawn_panel(ui, &mut restart.spawn);
+                    changed |= restart.spawn != spawn;
+
+                    restart.edited |= changed;
+                    response.restart = ui.button("Restart simulation").clicked();
+                });
+
+            restart.open = section.body_returned.is_some();
+        });
+}
+
+fn species_panel(ui: &mut egui::Ui, species: &mut SpeciesSettings) {
+    ui.add(egui::Slider::new(&mut species.move_speed, 0.0..=500.0).text("move speed"));
+    ui.add(egui::Slider::new(&mut species.turn_speed, -50.0..=50.0).text("turn speed"));
+    ui.add(egui::Slider::new(&mut species.sensor_angle_degrees, 0.0..=180.0).text("sensor angle"));
+    ui.add(egui::Slider::new(&mut species.sensor_offset_dst, 0.0..=100.0).text("sensor offset"));
+    ui.add(egui::Slider::new(&mut species.sensor_size, 0..=MAX_SENSOR_SIZE).text("sensor size"));
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut species.color);
+        ui.label("color");
+    });
+}
+
+fn fit_panel(ui: &mut egui::Ui, fit: &mut Fit) {
+    egui::ComboBox::from_label("fit")
+        .selected_text(fit.name())
+        .show_ui(ui, |ui| {
+            for variant in Fit::value_variants() {
+                ui.selectable_value(fit, *variant, variant.name());
+            }
+        });
+}
+
+fn boundary_panel(ui: &mut egui::Ui, boundary: &mut BoundaryMode) {
+    egui::ComboBox::from_label("boundary")
+        .selected_text(boundary.name())
+        .show_ui(ui, |ui| {
+            for variant in BoundaryMode::value_variants() {
+                ui.selectable_value(boundary, *variant, variant.name());
+            }
+        });
+}
+
+fn spawn_panel(ui: &mut egui::Ui, spawn: &mut SpawnMode) {
+    egui::ComboBox::from_label("spawn")
+        .selected_text(spawn.name())
+        .show_ui(ui, |ui| {
+            for kind in SpawnKind::value_variants() {
+                let preset = SpawnMode::from(*kind);
+                let selected = preset.name() == spawn.name();
+
+                if ui.selectable_label(selected, preset.name()).clicked() && !selected {
+                    *spawn = preset;
+                }
+            }
+        });
+
+    match spawn {
+        SpawnMode::Circle { radius } | SpawnMode::Ring { radius, .. } => {
+            ui.horizontal(|ui| {
+                ui.label("radius");
+                ui.add(egui::DragValue::new(radius).clamp_range(0.0..=8192.0));
+            });
+        }
+        SpawnMode::Rect { width, height } => {
+            ui.horizontal(|ui| {
+                ui.label("extent");
+                ui.add(egui::DragValue::new(width).clamp_range(0.0..=8192.0));
+                ui.add(egui::DragValue::new(height).clamp_range(0.0..=8192.0));
+            });
+        }
+        SpawnMode::Point | SpawnMode::Random => {}
+    }
+}
+
+fn translate_key(key: event::VirtualKeyCode) -> Option<egui::Key> {
+    use event::VirtualKeyCode as Code;
+
+    let key = match key {
+        Code::Down => egui::Key::ArrowDown,
+        Code::Left => egui::Key::ArrowLeft,
+        Code::Right => egui::Key::ArrowRight,
+        Code::Up => egui::Key::ArrowUp,
+        Code::Escape => egui::Key::Escape,
+        Code::Tab => egui::Key::Tab,
+        Code::Back => egui::Key::Backspace,
+        Code::Return | Code::NumpadEnter => egui::Key::Enter,
+        Code::Space => egui::Key::Space,
+        Code::Insert => egui::Key::Insert,
+        Code::Delete => egui::Key::Delete,
+        Code::Home => egui::Key::Home,
+        Code::End => egui::Key::End,
+        Code::PageUp => egui::Key::PageUp,
+        Code::PageDown => egui::Key::PageDown,
+        Code::Minus | Code::NumpadSubtract => egui::Key::Minus,
+        Code::Equals | Code::Plus | Code::NumpadAdd => egui::Key::PlusEquals,
+        Code::A => egui::Key::A,
+        Code::C => egui::Key::C,
+        Code::V => egui::Key::V,
+        Code::X => egui::Key::X,
+        Code::Z => egui::Key::Z,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_keeps_the_running_agent_count() {
+        let mut settings = AppSettings::default();
+        let mut restart = Restart::new(&settings);
+
+        // Changed at runtime with `=` or the count field, then restarted.
+        settings.num_agents = 12_345;
+        restart.refresh(&settings);
+        restart.apply(&mut settings);
+
+        assert_eq!(settings.num_agents, 12_345);
+    }
+
+    #[test]
+    fn restart_keeps_edits_while_open() {
+        let mut settings = AppSettings::default();
+        let mut restart = Restart::new(&settings);
+
+        restart.open = true;
+        restart.num_agents = 500;
+        restart.edited = true;
+
+        settings.num_agents = 12_345;
+        restart.refresh(&settings);
+        restart.apply(&mut settings);
+        assert_eq!(settings.num_agents, 500);
+
+        // Once applied, the values follow the running settings again.
+        settings.num_agents = 700;
+        restart.refresh(&settings);
+        assert_eq!(restart.num_agents, 700);
+    }
+
+    #[test]
+    fn closing_the_section_drops_edits() {
+        let settings = AppSettings::default();
+        let mut restart = Restart::new(&settings);
+
+        restart.num_agents = 500;
+        restart.edited = true;
+        restart.refresh(&settings);
+
+        assert_eq!(restart.num_agents, settings.num_agents);
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fs, path::PathBuf};
+
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    app, capture, clock::StepClock, cpu::CpuSimulation, pipeline::Viewport, settings::AppSettings,
+    simulation::Backend, snapshot::Snapshot,
+};
+
+/// Frames are rendered as they would appear on an sRGB window surface.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+pub struct HeadlessOptions {
+    pub frames: usize,
+    /// Save every this many frames. The final frame is always saved.
+    pub every: Option<usize>,
+    pub out: PathBuf,
+    /// Simulate on the CPU even when the adapter has compute support.
+    pub cpu: bool,
+}
+
+/// Runs the simulation without a window, writing PNG frames to `options.out`.
+/// Starts from `snapshot` when given, otherwise from a fresh spawn. Falls
+/// back to the CPU when there is no adapter at all.
+pub fn run(
+    settings: AppSettings,
+    snapshot: Option<&Snapshot>,
+    options: &HeadlessOptions,
+) -> Result<(), image::ImageError> {
+    pretty_env_logger::init();
+
+    fs::create_dir_all(&options.out)?;
+
+    match pollster::block_on(request_device()) {
+        Some((adapter, device, queue)) => {
+            let backend = Backend::for_adapter(&adapter, options.cpu);
+
+            run_on_adapter(backend, settings, snapshot, options, &device, &queue)
+        }
+        None => {
+            log::warn!("No GPU or fallback adapters found, simulating on the CPU");
+
+            run_cpu(settings, snapshot, options)
+        }
+    }
+}
+
+fn run_on_adapter(
+    backend: Backend,
+    settings: AppSettings,
+    snapshot: Option<&Snapshot>,
+    options: &HeadlessOptions,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Result<(), image::ImageError> {
+    let size = PhysicalSize::new(settings.width, settings.height);
+
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("slime::headless::target"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let viewport = Viewport {
+        width: size.width,
+        height: size.height,
+    };
+
+    let mut simulation = match snapshot {
+        Some(snapshot) => backend.resume(snapshot, FORMAT, viewport, device, queue),
+        None => backend.start(settings, FORMAT, viewport, device, queue),
+    };
+
+    // Each frame stands for one step's worth of real time.
+    let frame_time = 1.0 / simulation.settings().sim_rate;
+
+    for frame in 1..=options.frames {
+        simulation.render(&view, device, queue, frame_time);
+
+        if should_save(options, frame) {
+            let pixels = capture::read_texture(device, queue, &target, 4);
+
+            save_frame(options, frame, &pixels, &size)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Same as `run_on_adapter` with the CPU backend, drawing frames without
+/// wgpu.
+fn run_cpu(
+    settings: AppSettings,
+    snapshot: Option<&Snapshot>,
+    options: &HeadlessOptions,
+) -> Result<(), image::ImageError> {
+    let mut simulation = match snapshot {
+        Some(snapshot) => CpuSimulation::from_snapshot(snapshot),
+        None => CpuSimulation::new(settings),
+    };
+
+    let size = PhysicalSize::new(simulation.settings().width, simulation.settings().height);
+
+    let frame_time = 1.0 / simulation.settings().sim_rate;
+    let mut clock = StepClock::default();
+
+    for frame in 1..=options.frames {
+        for _ in 0..clock.advance(simulation.settings(), frame_time) {
+            simulation.step();
+        }
+
+        if should_save(options, frame) {
+            save_frame(options, frame, &simulation.image(), &size)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn should_save(options: &HeadlessOptions, frame: usize) -> bool {
+    frame == options.frames
+        || options
+            .every
+            .is_some_and(|every| frame.is_multiple_of(every))
+}
+
+fn save_frame(
+    options: &HeadlessOptions,
+    frame: usize,
+    pixels: &[u8],
+    size: &PhysicalSize<u32>,
+) -> Result<(), image::ImageError> {
+    let path = options.out.join(format!("frame_{:06}.png", frame));
+
+    image::save_buffer(
+        &path,
+        pixels,
+        size.width,
+        size.height,
+        image::ColorType::Rgba8,
+    )?;
+
+    log::info!("Wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Picks an adapter without a surface, falling back to a software adapter
+/// when no hardware one is available.
+pub(crate) async fn request_device() -> Option<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+
+    let backends = wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all);
+
+    let adapter =
+        match wgpu::util::initialize_adapter_from_env_or_default(&instance, backends, None).await {
+            Some(adapter) => adapter,
+            None => app::request_fallback_adapter(&instance, None).await?,
+        };
+
+    log::info!("Using adapter {}", adapter.get_info().name);
+
+    let (device, queue) = app::request_device(&adapter).await;
+
+    Some((adapter, device, queue))
+}
@@ -1,42 +1,76 @@
+use std::path::PathBuf;
+
+use clap::Parser;
 use instant::Instant;
 
-use app::{App, AppSettings};
-use pipeline::TimeBuffer;
-use shader_pipeline::ShaderPipeline;
+use app::App;
+use brush::Pointer;
+use cli::Cli;
+use gui::{Gui, PanelStatus};
+use pipeline::Viewport;
+use simulation::{Backend, Simulation};
+use snapshot::Snapshot;
 use winit::{
-    dpi::PhysicalSize,
     event::{self, Event, WindowEvent},
     event_loop::ControlFlow,
 };
 
+mod agent_buffers;
+mod agents;
 mod app;
+mod brush;
+mod capture;
+mod cli;
+mod clock;
+mod controls;
+mod cpu;
+mod cpu_pipeline;
+mod food;
+mod gui;
+mod headless;
+mod obstacles;
 mod pipeline;
 mod runner;
+mod settings;
 mod shader_pipeline;
+mod simulation;
+mod snapshot;
+mod spawn;
 
 fn main() {
-    let settings = AppSettings {
-        width: 800,
-        height: 600,
+    let cli = Cli::parse();
 
-        num_agents: 750_000,
-        steps_per_frame: 1,
+    let snapshot = cli.load.as_ref().map(|path| match Snapshot::load(path) {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            eprintln!("slime: {}", err);
+            std::process::exit(2);
+        }
+    });
 
-        move_speed: 50.0,
-        turn_speed: -3.0,
+    let settings = match &snapshot {
+        Some(snapshot) => snapshot.settings.clone(),
+        None => match cli.settings() {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!("slime: {}", err);
+                std::process::exit(2);
+            }
+        },
+    };
 
-        sensor_angle_degrees: 112.0,
-        sensor_offset_dst: 20.0,
-        sensor_size: 1,
+    if let Some(options) = cli.headless() {
+        if let Err(err) = headless::run(settings, snapshot.as_ref(), &options) {
+            eprintln!("slime: {}", err);
+            std::process::exit(1);
+        }
 
-        trail_weight: 2.0,
-        decay_rate: 0.75,
-        diffuse_rate: 5.0,
+        return;
+    }
 
-        agents_only: false,
-    };
+    let force_cpu = cli.cpu;
 
-    runner::run_app(settings, start);
+    runner::run_app(settings, move |app| start(app, snapshot, force_cpu));
 }
 
 fn start(
@@ -50,14 +84,15 @@ fn start(
         device,
         queue,
     }: App,
+    snapshot: Option<Snapshot>,
+    force_cpu: bool,
 ) {
     let capabilities = surface.get_capabilities(&adapter);
     let surface_format = capabilities
         .formats
         .iter()
         .copied()
-        .filter(|f| f.describe().srgb)
-        .next()
+        .find(|f| f.describe().srgb)
         .unwrap_or(capabilities.formats[0]);
 
     let mut surface_configuration = wgpu::SurfaceConfiguration {
@@ -72,10 +107,34 @@ fn start(
 
     surface.configure(&device, &surface_configuration);
 
-    let size = PhysicalSize::new(settings.width, settings.height);
-    let mut shader_pipeline = ShaderPipeline::new(settings, &size, &surface_configuration, &device);
+    let mut gui = Gui::new(&device, surface_format, &window, &settings);
 
-    let start_time = Instant::now();
+    let backend = Backend::for_adapter(&adapter, force_cpu);
+
+    let mut simulation = match &snapshot {
+        Some(snapshot) => backend.resume(
+            snapshot,
+            surface_format,
+            viewport(&surface_configuration),
+            &device,
+            &queue,
+        ),
+        None => backend.start(
+            settings,
+            surface_format,
+            viewport(&surface_configuration),
+            &device,
+            &queue,
+        ),
+    };
+
+    // The most recent snapshot saved this session, reloaded with F9.
+    let mut last_snapshot = None;
+
+    let mut last_frame = Instant::now();
+    let mut fps = 0.0;
+    let mut modifiers = event::ModifiersState::empty();
+    let mut pointer = Pointer::default();
 
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -89,13 +148,63 @@ fn start(
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
 
-                let time_buffer = TimeBuffer {
-                    time: start_time.elapsed().as_micros() as u32,
-                    delta_time: 0.005,
+                // A minimized window reports a zero size, which is skipped.
+                if simulation.settings().resize_with_window {
+                    simulation.resize_simulation(&window.inner_size(), &device, &queue);
+                }
+
+                let frame_time = last_frame.elapsed().as_secs_f32();
+                last_frame = Instant::now();
+
+                simulation.set_brush(pointer.stroke(
+                    simulation.settings(),
+                    &viewport(&surface_configuration),
+                    modifiers,
+                ));
+                simulation.render(&view, &device, &queue, frame_time);
+
+                if frame_time > 0.0 {
+                    fps = fps * 0.9 + 0.1 / frame_time;
+                }
+
+                let status = PanelStatus {
+                    fps,
+                    paused: simulation.is_paused(),
+                    step: simulation.step_count(),
                 };
+                let mut settings = simulation.settings().clone();
+                let response = gui.draw(&device, &queue, &view, &window, &status, &mut settings);
 
-                shader_pipeline.render(&view, &device, &queue, &time_buffer);
                 frame.present();
+
+                if response.toggle_pause {
+                    simulation.toggle_pause();
+                }
+
+                if response.step > 0 {
+                    simulation.step(response.step);
+                }
+
+                if let Some(count) = response.num_agents {
+                    simulation.set_agent_count(count, &device, &queue);
+                }
+
+                if response.restart {
+                    match settings.validate() {
+                        Ok(()) => {
+                            simulation = backend.start(
+                                settings,
+                                surface_format,
+                                viewport(&surface_configuration),
+                                &device,
+                                &queue,
+                            );
+                        }
+                        Err(err) => log::error!("Not restarting: {}", err),
+                    }
+                } else if &settings != simulation.settings() {
+                    simulation.set_settings(&queue, &settings);
+                }
             }
             Event::MainEventsCleared => {
                 window.request_redraw();
@@ -107,6 +216,11 @@ fn start(
                 surface_configuration.width = size.width.max(1);
                 surface_configuration.height = size.height.max(1);
                 surface.configure(&device, &surface_configuration);
+
+                simulation.resize(&queue, viewport(&surface_configuration));
+            }
+            Event::WindowEvent { event, .. } if gui.handle_event(&event) => {
+                pointer.handle_event(&event, true);
             }
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::KeyboardInput {
@@ -130,7 +244,148 @@ fn start(
                         },
                     ..
                 } => {
-                    shader_pipeline.swap_buffers();
+                    simulation.swap_buffers();
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::P),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    save_screenshot(simulation.as_ref(), &device, &queue);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::X),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    save_trail_map(simulation.as_ref(), &device, &queue);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::Space),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    simulation.toggle_pause();
+
+                    let state = if simulation.is_paused() {
+                        "paused"
+                    } else {
+                        "running"
+                    };
+                    window.set_title(&format!("Slime - {}", state));
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::Period),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let count = if modifiers.shift() {
+                        simulation.settings().frame_advance
+                    } else {
+                        1
+                    };
+
+                    simulation.step(count);
+                    window.set_title(&format!("Slime - paused, stepping {}", count));
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::F5),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    last_snapshot = save_snapshot(simulation.as_ref(), &device, &queue)
+                        .or(last_snapshot.take());
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::F9),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => match last_snapshot.as_deref().map(Snapshot::load) {
+                    Some(Ok(snapshot)) => {
+                        simulation = backend.resume(
+                            &snapshot,
+                            surface_format,
+                            viewport(&surface_configuration),
+                            &device,
+                            &queue,
+                        );
+                        gui.reset_restart();
+                        window.set_title("Slime - snapshot loaded");
+                    }
+                    Some(Err(err)) => log::error!("{}", err),
+                    None => log::warn!("No snapshot saved yet, press F5 first"),
+                },
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(event::VirtualKeyCode::Back),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    simulation = backend.start(
+                        simulation.settings().clone(),
+                        surface_format,
+                        viewport(&surface_configuration),
+                        &device,
+                        &queue,
+                    );
+                    gui.reset_restart();
+                    window.set_title("Slime - reset");
+                }
+                WindowEvent::ModifiersChanged(state) => {
+                    modifiers = state;
+                }
+                WindowEvent::CursorMoved { .. }
+                | WindowEvent::CursorLeft { .. }
+                | WindowEvent::MouseInput { .. } => {
+                    pointer.handle_event(&event, false);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        event::KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state: event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let mut settings = simulation.settings().clone();
+                    let shift = modifiers.shift();
+
+                    if let Some(count) = controls::agent_count(settings.num_agents, key, shift) {
+                        simulation.set_agent_count(count, &device, &queue);
+                        window.set_title(&format!("Slime - {} agents", count));
+                    } else if let Some(change) = controls::nudge(&mut settings, key, shift) {
+                        log::info!("{}", change);
+                        window.set_title(&format!("Slime - {}", change));
+                        simulation.set_settings(&queue, &settings);
+                    }
                 }
                 _ => {}
             },
@@ -138,3 +393,52 @@ fn start(
         }
     });
 }
+
+fn save_screenshot(simulation: &dyn Simulation, device: &wgpu::Device, queue: &wgpu::Queue) {
+    let Some(image) = simulation.screenshot(device, queue) else {
+        log::error!("Screenshots are not supported for this surface format");
+        return;
+    };
+
+    let path = capture::timestamped_path("png");
+
+    match image.save(&path) {
+        Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+        Err(err) => log::error!("Could not save {}: {}", path.display(), err),
+    }
+}
+
+fn save_snapshot(
+    simulation: &dyn Simulation,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> Option<PathBuf> {
+    let path = capture::timestamped_path("snap");
+
+    match simulation.snapshot(device, queue).save(&path) {
+        Ok(()) => {
+            log::info!("Saved snapshot to {}", path.display());
+            Some(path)
+        }
+        Err(err) => {
+            log::error!("{}", err);
+            None
+        }
+    }
+}
+
+fn save_trail_map(simulation: &dyn Simulation, device: &wgpu::Device, queue: &wgpu::Queue) {
+    let path = capture::timestamped_path("exr");
+
+    match simulation.trail_map_image(device, queue).save(&path) {
+        Ok(()) => log::info!("Saved trail map to {}", path.display()),
+        Err(err) => log::error!("Could not save {}: {}", path.display(), err),
+    }
+}
+
+fn viewport(surface_configuration: &wgpu::SurfaceConfiguration) -> Viewport {
+    Viewport {
+        width: surface_configuration.width,
+        height: surface_configuration.height,
+    }
+}
@@ -0,0 +1,61 @@
+//! Walls agents cannot enter. The mask lives on the CPU as one byte per
+//! texel, like the snapshot stores it, and is uploaded to an `R8Unorm`
+//! texture that the shaders read as 0 for open space and 1 for walls.
+
+use winit::dpi::PhysicalSize;
+
+/// Mask value for a wall.
+pub const WALL: u8 = 255;
+
+pub fn create_texture(device: &wgpu::Device, size: &PhysicalSize<u32>) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("slime::shader::simulation::obstacle_map"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+
+pub fn upload(queue: &wgpu::Queue, texture: &wgpu::Texture, mask: &[u8]) {
+    let size = texture.size();
+
+    queue.write_texture(
+        texture.as_image_copy(),
+        mask,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(size.width),
+            rows_per_image: std::num::NonZeroU32::new(size.height),
+        },
+        size,
+    );
+}
+
+/// Nearest-neighbour resample, so walls stay walls at any size. Works on
+/// any per-texel map, the food map included.
+pub fn resample<T: Copy>(
+    mask: &[T],
+    old_size: &PhysicalSize<u32>,
+    new_size: &PhysicalSize<u32>,
+) -> Vec<T> {
+    // Texel centers of the new mask, mapped into the old one.
+    let source = |index: u32, old: u32, new: u32| {
+        ((2 * index as u64 + 1) * old as u64 / (2 * new as u64)) as usize
+    };
+
+    (0..new_size.height)
+        .flat_map(|y| {
+            let row = source(y, old_size.height, new_size.height) * old_size.width as usize;
+
+            (0..new_size.width).map(move |x| mask[row + source(x, old_size.width, new_size.width)])
+        })
+        .collect()
+}
@@ -1,11 +1,17 @@
-use crate::app::AppSettings;
+use crate::settings::AppSettings;
 
+mod agent_ranges;
 mod blit_pipeline;
 mod clear_pipeline;
+mod compact_pipeline;
 mod copy_agent_map_pipeline;
+mod deposit_pipeline;
 mod diffuse_pipeline;
+mod gui_pipeline;
 mod render_pipeline;
+mod resample_pipeline;
 mod slime_sim_pipeline;
+mod spawn_pipeline;
 
 pub trait Pipeline {
     type Bind;
@@ -13,14 +19,20 @@ pub trait Pipeline {
 
     fn new(device: &wgpu::Device, settings: &AppSettings, bind: &Self::Bind) -> Self;
     fn update(&mut self, queue: &wgpu::Queue, update: &Self::Update);
+    fn set_settings(&mut self, _queue: &wgpu::Queue, _settings: &AppSettings) {}
     fn execute(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView);
 }
 
 pub use self::{
     blit_pipeline::{BlitPipeline, BlitSettings},
     clear_pipeline::{ClearPipeline, ClearSetup},
+    compact_pipeline::{CompactPipeline, CompactSetup},
     copy_agent_map_pipeline::CopyAgentMapPipeline,
+    deposit_pipeline::{DepositPipeline, DepositSetup},
     diffuse_pipeline::{DiffusePipeline, DiffuseSettings},
-    render_pipeline::{RenderPipeline, RenderSettings},
+    gui_pipeline::{GuiPipeline, ScreenDescriptor},
+    render_pipeline::{window_to_simulation, RenderPipeline, RenderSettings, Viewport},
+    resample_pipeline::{ResamplePipeline, ResampleSetup},
     slime_sim_pipeline::{SlimeSimPipeline, SlimeSimSetup, TimeBuffer},
+    spawn_pipeline::{SpawnBuffer, SpawnPipeline, SpawnSetup},
 };
@@ -0,0 +1,178 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::agent_buffers::AgentBuffers;
+
+/// Workgroups a dispatch may have along one dimension on any device.
+const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65_535;
+
+/// The agent kernels' view of each agent buffer: which of its slots are in
+/// use, where they start in the population, and how the dispatch over them
+/// is folded into rows of workgroups to stay under the per-dimension limit.
+pub struct AgentRanges {
+    ranges: Vec<(wgpu::Buffer, AgentRange, u32)>,
+    group_size: u32,
+}
+
+impl AgentRanges {
+    pub fn new(
+        device: &wgpu::Device,
+        agents: &AgentBuffers,
+        num_agents: u32,
+        group_size: u32,
+    ) -> Self {
+        let ranges = agents
+            .chunks()
+            .map(|chunk| {
+                let (range, rows) = AgentRange::fit(
+                    chunk.first_index as u32,
+                    chunk.len as u32,
+                    num_agents,
+                    group_size,
+                );
+
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("slime::shader::agent_range_buffer"),
+                    contents: bytemuck::bytes_of(&range),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+
+                (buffer, range, rows)
+            })
+            .collect();
+
+        Self { ranges, group_size }
+    }
+
+    /// The uniform for each agent buffer, in order.
+    pub fn buffers(&self) -> impl Iterator<Item = &wgpu::Buffer> {
+        self.ranges.iter().map(|(buffer, _, _)| buffer)
+    }
+
+    /// Runs the agents in the first `num_agents` slots of the population.
+    pub fn set_agent_count(&mut self, queue: &wgpu::Queue, num_agents: u32) {
+        for (buffer, range, rows) in &mut self.ranges {
+            (*range, *rows) = AgentRange::fit(
+                range.first_index,
+                range.capacity,
+                num_agents,
+                self.group_size,
+            );
+            queue.write_buffer(buffer, 0, bytemuck::bytes_of(range));
+        }
+    }
+
+    /// Dispatches the bound pipeline over every agent buffer with agents in
+    /// it, `bind_groups` holding one bind group per buffer.
+    pub fn dispatch<'a>(
+        &'a self,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+        bind_groups: &'a [wgpu::BindGroup],
+    ) {
+        for ((_, range, rows), bind_group) in self.ranges.iter().zip(bind_groups) {
+            if range.num_agents == 0 {
+                continue;
+            }
+
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(range.row_len / self.group_size, *rows, 1);
+        }
+    }
+}
+
+/// Matches `AgentRange` in the agent shaders. An agent's index in its buffer
+/// is `id.x + id.y * row_len`, and in the population `first_index` more.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct AgentRange {
+    first_index: u32,
+    /// Slots of this buffer in use, dead agents included.
+    num_agents: u32,
+    row_len: u32,
+    capacity: u32,
+}
+
+impl AgentRange {
+    /// The range of a buffer of `capacity` slots from `first_index` on, when
+    /// the first `num_agents` slots of the population are in use, and the
+    /// rows of workgroups its dispatch takes.
+    fn fit(first_index: u32, capacity: u32, num_agents: u32, group_size: u32) -> (Self, u32) {
+        let num_agents = num_agents.saturating_sub(first_index).min(capacity);
+        let groups = num_agents.div_ceil(group_size);
+        let rows = groups.div_ceil(MAX_WORKGROUPS_PER_DIMENSION);
+
+        let row_len = match rows {
+            0 => 0,
+            rows => groups.div_ceil(rows) * group_size,
+        };
+
+        let range = AgentRange {
+            first_index,
+            num_agents,
+            row_len,
+            capacity,
+        };
+
+        (range, rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GROUP_SIZE: u32 = 64;
+
+    /// Checks the dispatch covers every agent of `range` within the
+    /// per-dimension limit, and returns how many slots it runs over.
+    fn dispatched(range: &AgentRange, rows: u32) -> u32 {
+        assert!(range.row_len / GROUP_SIZE <= MAX_WORKGROUPS_PER_DIMENSION);
+        assert!(rows <= MAX_WORKGROUPS_PER_DIMENSION);
+        assert_eq!(range.row_len % GROUP_SIZE, 0);
+        assert!(range.row_len * rows >= range.num_agents);
+
+        range.row_len * rows
+    }
+
+    #[test]
+    fn fits_in_one_row_up_to_the_limit() {
+        let num_agents = MAX_WORKGROUPS_PER_DIMENSION * GROUP_SIZE;
+        let (range, rows) = AgentRange::fit(0, u32::MAX, num_agents, GROUP_SIZE);
+
+        assert_eq!(rows, 1);
+        assert_eq!(range.num_agents, num_agents);
+        assert_eq!(dispatched(&range, rows), num_agents);
+    }
+
+    #[test]
+    fn folds_into_rows_just_past_the_limit() {
+        for extra in [1, GROUP_SIZE, GROUP_SIZE + 1] {
+            let num_agents = MAX_WORKGROUPS_PER_DIMENSION * GROUP_SIZE + extra;
+            let (range, rows) = AgentRange::fit(0, u32::MAX, num_agents, GROUP_SIZE);
+
+            assert_eq!(rows, 2);
+            assert_eq!(range.num_agents, num_agents);
+
+            // Rows are balanced, so the spare slots stay under a row of groups.
+            assert!(dispatched(&range, rows) - num_agents < 2 * GROUP_SIZE);
+        }
+    }
+
+    #[test]
+    fn takes_the_buffer_share_of_the_population() {
+        // The population ends inside the buffer.
+        let (range, rows) = AgentRange::fit(1_000, 500, 1_200, GROUP_SIZE);
+        assert_eq!(range.num_agents, 200);
+        assert_eq!(rows, 1);
+        assert_eq!(dispatched(&range, rows), 256);
+
+        // The buffer is full.
+        let (range, _) = AgentRange::fit(1_000, 500, 5_000, GROUP_SIZE);
+        assert_eq!(range.num_agents, 500);
+
+        // The population ends before the buffer.
+        let (range, rows) = AgentRange::fit(1_000, 500, 800, GROUP_SIZE);
+        assert_eq!(range.num_agents, 0);
+        assert_eq!((range.row_len, rows), (0, 0));
+    }
+}
@@ -16,7 +16,11 @@ impl super::Pipeline for BlitPipeline {
     type Bind = BlitSettings;
     type Update = ();
 
-    fn new(device: &wgpu::Device, _settings: &crate::app::AppSettings, bind: &Self::Bind) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        _settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("slime::shader::blit"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
@@ -15,7 +15,11 @@ impl super::Pipeline for ClearPipeline {
     type Bind = ClearSetup;
     type Update = ();
 
-    fn new(device: &wgpu::Device, _settings: &crate::app::AppSettings, bind: &Self::Bind) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        _settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
         let slime_sim_compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("slime::shader::slime_sim_compute"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
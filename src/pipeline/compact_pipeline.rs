@@ -0,0 +1,231 @@
+use std::{borrow::Cow, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    agent_buffers::{AgentBuffers, CHUNK_ALIGNMENT},
+    shader_pipeline::Agent,
+};
+
+use super::agent_ranges::{AgentRange, AgentRanges};
+
+const AGENTS_PER_GROUP: u32 = CHUNK_ALIGNMENT as u32;
+
+/// Packs the live agents into the front of another set of buffers, keeping
+/// their order, so deleted agents' slots are reclaimed without a readback.
+pub struct CompactPipeline {
+    count_pipeline: wgpu::ComputePipeline,
+    sum_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+    /// One list per destination buffer, holding one bind group for each
+    /// source buffer.
+    bind_groups: Vec<Vec<wgpu::BindGroup>>,
+    agent_ranges: AgentRanges,
+}
+
+impl super::Pipeline for CompactPipeline {
+    type Bind = CompactSetup;
+    type Update = ();
+
+    fn new(
+        device: &wgpu::Device,
+        _settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("slime::shader::compact"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../../shaders/compact.wgsl"
+            ))),
+        });
+
+        let agent_binding = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: wgpu::BufferSize::new(
+                    std::mem::size_of::<Agent>() as wgpu::BufferAddress
+                ),
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("slime::shader::compact_bind_group_layout"),
+            entries: &[
+                agent_binding(0, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<AgentRange>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<u32>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                agent_binding(3, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Destination>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let agent_ranges =
+            AgentRanges::new(device, &bind.agents, bind.num_agents, AGENTS_PER_GROUP);
+
+        // Live agents per group of slots, then where each group's first one
+        // lands.
+        let groups = bind.num_agents.div_ceil(AGENTS_PER_GROUP).max(1);
+        let group_counts = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("slime::shader::compact_group_counts"),
+            size: (groups as usize * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_groups = bind
+            .compacted
+            .chunks()
+            .map(|destination| {
+                let destination_buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("slime::shader::compact_destination_buffer"),
+                        contents: bytemuck::bytes_of(&Destination {
+                            first_index: destination.first_index as u32,
+                            capacity: destination.len as u32,
+                        }),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+
+                bind.agents
+                    .chunks()
+                    .zip(agent_ranges.buffers())
+                    .map(|(source, range_buffer)| {
+                        device.create_bind_group(&wgpu::BindGroupDescriptor {
+                            label: Some("slime::shader::compact::bind_group"),
+                            layout: &bind_group_layout,
+                            entries: &[
+                                wgpu::BindGroupEntry {
+                                    binding: 0,
+                                    resource: source.buffer.as_entire_binding(),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 1,
+                                    resource: range_buffer.as_entire_binding(),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 2,
+                                    resource: group_counts.as_entire_binding(),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 3,
+                                    resource: destination.buffer.as_entire_binding(),
+                                },
+                                wgpu::BindGroupEntry {
+                                    binding: 4,
+                                    resource: destination_buffer.as_entire_binding(),
+                                },
+                            ],
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("slime::shader::compact_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        Self {
+            count_pipeline: create_pipeline("slime::shader::compact_count_pipeline", "count_live"),
+            sum_pipeline: create_pipeline("slime::shader::compact_sum_pipeline", "sum_groups"),
+            scatter_pipeline: create_pipeline("slime::shader::compact_scatter_pipeline", "scatter"),
+            bind_groups,
+            agent_ranges,
+        }
+    }
+
+    fn update(&mut self, _queue: &wgpu::Queue, _update: &Self::Update) {}
+
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, _frame: &wgpu::TextureView) {
+        let Some(first) = self.bind_groups.first() else {
+            return;
+        };
+
+        encoder.push_debug_group("compact agents");
+        {
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+
+            // Counting and summing do not touch the destination, so any
+            // destination's bind groups do.
+            compute_pass.set_pipeline(&self.count_pipeline);
+            self.agent_ranges.dispatch(&mut compute_pass, first);
+
+            compute_pass.set_pipeline(&self.sum_pipeline);
+            compute_pass.set_bind_group(0, &first[0], &[]);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+
+            compute_pass.set_pipeline(&self.scatter_pipeline);
+            for bind_groups in &self.bind_groups {
+                self.agent_ranges.dispatch(&mut compute_pass, bind_groups);
+            }
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+pub struct CompactSetup {
+    pub agents: Rc<AgentBuffers>,
+    /// Slots of `agents` in use, dead agents included.
+    pub num_agents: u32,
+    /// Where the live agents go, from the first slot on. Agents past its
+    /// capacity are left out.
+    pub compacted: Rc<AgentBuffers>,
+}
+
+/// Matches `Destination` in `compact.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Destination {
+    first_index: u32,
+    capacity: u32,
+}
@@ -2,21 +2,29 @@ use std::borrow::Cow;
 
 use crate::shader_pipeline::Agent;
 
-use super::{SlimeSimSetup, TimeBuffer};
+use super::{
+    agent_ranges::{AgentRange, AgentRanges},
+    SlimeSimSetup, TimeBuffer,
+};
 
-const AGENTS_PER_GROUP: f32 = 16.0;
+const AGENTS_PER_GROUP: u32 = 16;
 
 pub struct CopyAgentMapPipeline {
     pipeline: wgpu::ComputePipeline,
-    bind_group: wgpu::BindGroup,
-    workgroup_count: u32,
+    /// One for each agent buffer.
+    bind_groups: Vec<wgpu::BindGroup>,
+    agent_ranges: AgentRanges,
 }
 
 impl super::Pipeline for CopyAgentMapPipeline {
     type Bind = SlimeSimSetup;
     type Update = TimeBuffer;
 
-    fn new(device: &wgpu::Device, settings: &crate::app::AppSettings, bind: &Self::Bind) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        _settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("slime::shader::copy"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
@@ -34,8 +42,7 @@ impl super::Pipeline for CopyAgentMapPipeline {
                         ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: false,
                         min_binding_size: wgpu::BufferSize::new(
-                            (std::mem::size_of::<Agent>() * settings.num_agents)
-                                as wgpu::BufferAddress,
+                            std::mem::size_of::<Agent>() as wgpu::BufferAddress
                         ),
                     },
                     count: None,
@@ -50,24 +57,52 @@ impl super::Pipeline for CopyAgentMapPipeline {
                     },
                     count: None,
                 },
-            ],
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: bind.binding.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(&bind.display_texture_view),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<AgentRange>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
                 },
             ],
         });
 
+        let agent_ranges =
+            AgentRanges::new(device, &bind.agents, bind.num_agents, AGENTS_PER_GROUP);
+
+        let bind_groups = bind
+            .agents
+            .chunks()
+            .zip(agent_ranges.buffers())
+            .map(|(chunk, range_buffer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: chunk.buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(
+                                &bind.display_texture_view,
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: range_buffer.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
         let diffuse_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("render"),
@@ -82,12 +117,10 @@ impl super::Pipeline for CopyAgentMapPipeline {
             module: &shader,
         });
 
-        let workgroup_count = (bind.num_agents as f32 / AGENTS_PER_GROUP).ceil() as u32;
-
         Self {
             pipeline: diffuse_pipeline,
-            bind_group,
-            workgroup_count,
+            bind_groups,
+            agent_ranges,
         }
     }
 
@@ -99,9 +132,16 @@ impl super::Pipeline for CopyAgentMapPipeline {
             let mut compute_pass =
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
             compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &self.bind_group, &[]);
-            compute_pass.dispatch_workgroups(self.workgroup_count, 1, 1);
+            self.agent_ranges
+                .dispatch(&mut compute_pass, &self.bind_groups);
         }
         encoder.pop_debug_group();
     }
 }
+
+impl CopyAgentMapPipeline {
+    /// Draws the agents in the first `num_agents` slots of the buffers.
+    pub fn set_agent_count(&mut self, queue: &wgpu::Queue, num_agents: u32) {
+        self.agent_ranges.set_agent_count(queue, num_agents);
+    }
+}
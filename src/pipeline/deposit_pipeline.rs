@@ -0,0 +1,230 @@
+use std::{borrow::Cow, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use super::TimeBuffer;
+
+const LENGTH_PER_GROUP: f32 = 8.0;
+
+/// Adds the agents counted by the simulation and the food map's attractant
+/// to the trail map, writing the result to the deposit map and clearing the
+/// counts.
+pub struct DepositPipeline {
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    globals: Globals,
+    globals_buffer: wgpu::Buffer,
+    time_buffer: wgpu::Buffer,
+    workgroup_count_x: u32,
+    workgroup_count_y: u32,
+}
+
+impl super::Pipeline for DepositPipeline {
+    type Bind = DepositSetup;
+    type Update = TimeBuffer;
+
+    fn new(
+        device: &wgpu::Device,
+        settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("slime::shader::deposit"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../../shaders/deposit.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("slime::shader::deposit_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Globals>() as wgpu::BufferAddress
+                        ),
+                        ty: wgpu::BufferBindingType::Uniform,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<TimeBuffer>() as wgpu::BufferAddress
+                        ),
+                        ty: wgpu::BufferBindingType::Uniform,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let globals = Globals {
+            width: bind.width,
+            height: bind.height,
+            trail_weight: settings.trail_weight,
+            fed: !settings.food.is_empty() as u32,
+        };
+
+        let time = TimeBuffer {
+            time: 0,
+            delta_time: 0.0,
+        };
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::deposit_globals_buffer"),
+            contents: bytemuck::bytes_of(&globals),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::deposit_time_buffer"),
+            contents: bytemuck::bytes_of(&time),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("slime::shader::deposit::bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: time_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&bind.trail_map_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: bind.deposit_counts.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&bind.deposit_map),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&bind.food_map),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("slime::shader::deposit_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("slime::shader::deposit_pipeline"),
+            layout: Some(&pipeline_layout),
+            entry_point: "cs_main",
+            module: &shader,
+        });
+
+        let workgroup_count_x = (bind.width as f32 / LENGTH_PER_GROUP).ceil() as u32;
+        let workgroup_count_y = (bind.height as f32 / LENGTH_PER_GROUP).ceil() as u32;
+
+        Self {
+            pipeline,
+            bind_group,
+            globals,
+            globals_buffer,
+            time_buffer,
+            workgroup_count_x,
+            workgroup_count_y,
+        }
+    }
+
+    fn update(&mut self, queue: &wgpu::Queue, update: &Self::Update) {
+        queue.write_buffer(&self.time_buffer, 0, bytemuck::bytes_of(update));
+    }
+
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &crate::settings::AppSettings) {
+        self.globals.trail_weight = settings.trail_weight;
+        self.globals.fed = !settings.food.is_empty() as u32;
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&self.globals));
+    }
+
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, _frame: &wgpu::TextureView) {
+        encoder.push_debug_group("deposit trails");
+        {
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.workgroup_count_x, self.workgroup_count_y, 1);
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+pub struct DepositSetup {
+    pub width: u32,
+    pub height: u32,
+    pub trail_map_texture: wgpu::TextureView,
+    pub deposit_counts: Rc<wgpu::Buffer>,
+    pub deposit_map: wgpu::TextureView,
+    pub food_map: wgpu::TextureView,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Globals {
+    width: u32,
+    height: u32,
+    trail_weight: f32,
+    fed: u32,
+}
@@ -3,6 +3,8 @@ use std::borrow::Cow;
 use bytemuck::{Pod, Zeroable};
 use wgpu::util::DeviceExt;
 
+use crate::brush::BrushBuffer;
+
 use super::TimeBuffer;
 
 const LENGTH_PER_GROUP: f32 = 8.0;
@@ -10,7 +12,10 @@ const LENGTH_PER_GROUP: f32 = 8.0;
 pub struct DiffusePipeline {
     pipeline: wgpu::ComputePipeline,
     bind_group: wgpu::BindGroup,
+    globals: Globals,
+    globals_buffer: wgpu::Buffer,
     time_buffer: wgpu::Buffer,
+    brush_buffer: wgpu::Buffer,
     workgroup_count_x: u32,
     workgroup_count_y: u32,
 }
@@ -19,7 +24,11 @@ impl super::Pipeline for DiffusePipeline {
     type Bind = DiffuseSettings;
     type Update = TimeBuffer;
 
-    fn new(device: &wgpu::Device, settings: &crate::app::AppSettings, bind: &Self::Bind) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("slime::shader::diffuse"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
@@ -74,6 +83,28 @@ impl super::Pipeline for DiffusePipeline {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<BrushBuffer>() as wgpu::BufferAddress
+                        ),
+                        ty: wgpu::BufferBindingType::Uniform,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -82,6 +113,8 @@ impl super::Pipeline for DiffusePipeline {
             height: bind.height,
             diffuse_rate: settings.diffuse_rate,
             decay_rate: settings.decay_rate,
+            boundary: settings.boundary.shader_index(),
+            _padding: [0; 3],
         };
 
         let time = TimeBuffer {
@@ -101,6 +134,12 @@ impl super::Pipeline for DiffusePipeline {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let brush_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::diffuse_brush_buffer"),
+            contents: bytemuck::bytes_of(&BrushBuffer::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &bind_group_layout,
@@ -115,11 +154,19 @@ impl super::Pipeline for DiffusePipeline {
                 },
                 wgpu::BindGroupEntry {
                     binding: 2,
-                    resource: wgpu::BindingResource::TextureView(&bind.trail_map_texture),
+                    resource: wgpu::BindingResource::TextureView(&bind.deposit_map),
                 },
                 wgpu::BindGroupEntry {
                     binding: 3,
-                    resource: wgpu::BindingResource::TextureView(&bind.diffuse_texture),
+                    resource: wgpu::BindingResource::TextureView(&bind.trail_map_texture),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&bind.obstacle_map),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: brush_buffer.as_entire_binding(),
                 },
             ],
         });
@@ -144,7 +191,10 @@ impl super::Pipeline for DiffusePipeline {
         Self {
             pipeline: diffuse_pipeline,
             bind_group,
+            globals,
+            globals_buffer,
             time_buffer,
+            brush_buffer,
             workgroup_count_x,
             workgroup_count_y,
         }
@@ -154,6 +204,13 @@ impl super::Pipeline for DiffusePipeline {
         queue.write_buffer(&self.time_buffer, 0, bytemuck::bytes_of(update));
     }
 
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &crate::settings::AppSettings) {
+        self.globals.diffuse_rate = settings.diffuse_rate;
+        self.globals.decay_rate = settings.decay_rate;
+        self.globals.boundary = settings.boundary.shader_index();
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&self.globals));
+    }
+
     fn execute(&self, encoder: &mut wgpu::CommandEncoder, _frame: &wgpu::TextureView) {
         encoder.push_debug_group("Render Pipeline");
         {
@@ -167,11 +224,21 @@ impl super::Pipeline for DiffusePipeline {
     }
 }
 
+impl DiffusePipeline {
+    /// Paints with `brush` on the steps to come, or stops when it is off.
+    pub fn set_brush(&self, queue: &wgpu::Queue, brush: &BrushBuffer) {
+        queue.write_buffer(&self.brush_buffer, 0, bytemuck::bytes_of(brush));
+    }
+}
+
 pub struct DiffuseSettings {
     pub width: u32,
     pub height: u32,
+    /// The trail map with this step's deposits, read.
+    pub deposit_map: wgpu::TextureView,
+    /// The trail map for the next step, written.
     pub trail_map_texture: wgpu::TextureView,
-    pub diffuse_texture: wgpu::TextureView,
+    pub obstacle_map: wgpu::TextureView,
 }
 
 #[repr(C)]
@@ -181,4 +248,6 @@ struct Globals {
     height: u32,
     diffuse_rate: f32,
     decay_rate: f32,
+    boundary: u32,
+    _padding: [u32; 3],
 }
@@ -0,0 +1,404 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+/// Paints tessellated egui output on top of an already rendered frame.
+///
+/// Unlike the simulation pipelines this one needs the device every frame to
+/// (re)create textures and vertex buffers, so it does not implement
+/// [`super::Pipeline`].
+pub struct GuiPipeline {
+    pipeline: wgpu::RenderPipeline,
+    globals_buffer: wgpu::Buffer,
+    globals_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    textures: HashMap<egui::TextureId, GuiTexture>,
+    draws: Vec<GuiDraw>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    index_buffer: Option<wgpu::Buffer>,
+}
+
+struct GuiTexture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+struct GuiDraw {
+    texture_id: egui::TextureId,
+    scissor: [u32; 4],
+    indices: std::ops::Range<u32>,
+    base_vertex: i32,
+}
+
+/// Size of the surface being painted on, in physical pixels.
+pub struct ScreenDescriptor {
+    pub width: u32,
+    pub height: u32,
+    pub pixels_per_point: f32,
+}
+
+impl GuiPipeline {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("slime::shader::gui"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../shaders/gui.wgsl"))),
+        });
+
+        let globals_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("slime::shader::gui_globals_bind_group_layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Globals>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                }],
+            });
+
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("slime::shader::gui_texture_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::gui_globals_buffer"),
+            contents: bytemuck::bytes_of(&Globals {
+                screen_size: [1.0, 1.0],
+                _padding: [0.0, 0.0],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("slime::shader::gui_globals_bind_group"),
+            layout: &globals_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: globals_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("slime::shader::gui_pipeline_layout"),
+            bind_group_layouts: &[&globals_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffers = [wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Unorm8x4],
+        }];
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("slime::shader::gui_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &vertex_buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            globals_buffer,
+            globals_bind_group,
+            texture_bind_group_layout,
+            textures: HashMap::new(),
+            draws: Vec::new(),
+            vertex_buffer: None,
+            index_buffer: None,
+        }
+    }
+
+    /// Uploads texture changes and the meshes of this frame.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures_delta: &egui::TexturesDelta,
+        primitives: &[egui::ClippedPrimitive],
+        screen: &ScreenDescriptor,
+    ) {
+        for (id, delta) in &textures_delta.set {
+            self.update_texture(device, queue, *id, delta);
+        }
+
+        for id in &textures_delta.free {
+            self.textures.remove(id);
+        }
+
+        let globals = Globals {
+            screen_size: [
+                screen.width as f32 / screen.pixels_per_point,
+                screen.height as f32 / screen.pixels_per_point,
+            ],
+            _padding: [0.0, 0.0],
+        };
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+
+        let mut vertices: Vec<egui::epaint::Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        self.draws.clear();
+
+        for primitive in primitives {
+            let mesh = match &primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) => mesh,
+                egui::epaint::Primitive::Callback(_) => continue,
+            };
+
+            let Some(scissor) = scissor_rect(primitive.clip_rect, screen) else {
+                continue;
+            };
+
+            let first_index = indices.len() as u32;
+            self.draws.push(GuiDraw {
+                texture_id: mesh.texture_id,
+                scissor,
+                indices: first_index..first_index + mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+            });
+
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        if self.draws.is_empty() {
+            return;
+        }
+
+        self.vertex_buffer = Some(
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("slime::shader::gui_vertex_buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+        );
+
+        self.index_buffer = Some(
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("slime::shader::gui_index_buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+        );
+    }
+
+    pub fn execute(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView) {
+        let (Some(vertex_buffer), Some(index_buffer)) = (&self.vertex_buffer, &self.index_buffer)
+        else {
+            return;
+        };
+
+        if self.draws.is_empty() {
+            return;
+        }
+
+        let color_attachments = [Some(wgpu::RenderPassColorAttachment {
+            view: frame,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: true,
+            },
+        })];
+
+        encoder.push_debug_group("Gui Pipeline");
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("slime::shader::gui_render_pass"),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            for draw in &self.draws {
+                let Some(texture) = self.textures.get(&draw.texture_id) else {
+                    continue;
+                };
+
+                let [x, y, width, height] = draw.scissor;
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(1, &texture.bind_group, &[]);
+                render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+            }
+        }
+        encoder.pop_debug_group();
+    }
+
+    fn update_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) {
+        let pixels: Vec<egui::Color32> = match &delta.image {
+            egui::ImageData::Color(image) => image.pixels.clone(),
+            egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+        };
+
+        let [width, height] = delta.image.size();
+        let size = wgpu::Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+
+        let origin = match delta.pos {
+            Some([x, y]) => wgpu::Origin3d {
+                x: x as u32,
+                y: y as u32,
+                z: 0,
+            },
+            None => {
+                let texture = self.create_texture(device, size, delta.options);
+                self.textures.insert(id, texture);
+                wgpu::Origin3d::ZERO
+            }
+        };
+
+        let Some(texture) = self.textures.get(&id) else {
+            log::warn!("Partial update for unknown gui texture {:?}", id);
+            return;
+        };
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(4 * width as u32),
+                rows_per_image: std::num::NonZeroU32::new(height as u32),
+            },
+            size,
+        );
+    }
+
+    fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        options: egui::TextureOptions,
+    ) -> GuiTexture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("slime::shader::gui_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let filter = |filter: egui::TextureFilter| match filter {
+            egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+            egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+        };
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("slime::shader::gui_sampler"),
+            mag_filter: filter(options.magnification),
+            min_filter: filter(options.minification),
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("slime::shader::gui_texture_bind_group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        GuiTexture {
+            texture,
+            bind_group,
+        }
+    }
+}
+
+/// Converts an egui clip rectangle in points to a scissor rectangle in pixels,
+/// clamped to the surface. Returns `None` when nothing would be visible.
+fn scissor_rect(clip_rect: egui::Rect, screen: &ScreenDescriptor) -> Option<[u32; 4]> {
+    let min_x = (clip_rect.min.x * screen.pixels_per_point).round() as u32;
+    let min_y = (clip_rect.min.y * screen.pixels_per_point).round() as u32;
+    let max_x = (clip_rect.max.x * screen.pixels_per_point).round() as u32;
+    let max_y = (clip_rect.max.y * screen.pixels_per_point).round() as u32;
+
+    let min_x = min_x.min(screen.width);
+    let min_y = min_y.min(screen.height);
+    let max_x = max_x.clamp(min_x, screen.width);
+    let max_y = max_y.clamp(min_y, screen.height);
+
+    if max_x == min_x || max_y == min_y {
+        return None;
+    }
+
+    Some([min_x, min_y, max_x - min_x, max_y - min_y])
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Globals {
+    screen_size: [f32; 2],
+    _padding: [f32; 2],
+}
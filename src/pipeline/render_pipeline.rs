@@ -3,19 +3,31 @@ use std::borrow::Cow;
 use bytemuck::{Pod, Zeroable};
 use wgpu::util::DeviceExt;
 
+use crate::settings::{Fit, MAX_SPECIES};
+
 pub struct RenderPipeline {
     pipeline: wgpu::RenderPipeline,
     bind_group: wgpu::BindGroup,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     index_count: usize,
+    species_colors_buffer: wgpu::Buffer,
+    transform_buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    fit: Fit,
+    viewport: Viewport,
 }
 
 impl super::Pipeline for RenderPipeline {
     type Bind = RenderSettings;
-    type Update = ();
+    type Update = Viewport;
 
-    fn new(device: &wgpu::Device, _settings: &crate::app::AppSettings, bind: &Self::Bind) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
         let draw_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("slime::shader::draw"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
@@ -48,6 +60,20 @@ impl super::Pipeline for RenderPipeline {
             usage: wgpu::BufferUsages::INDEX,
         });
 
+        let species_colors_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::species_colors_buffer"),
+            contents: bytemuck::bytes_of(&SpeciesColors::from(settings)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let transform = Transform::new(settings.fit, bind.width, bind.height, &bind.viewport);
+
+        let transform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::transform_buffer"),
+            contents: bytemuck::bytes_of(&transform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
             entries: &[
@@ -67,6 +93,40 @@ impl super::Pipeline for RenderPipeline {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<SpeciesColors>() as wgpu::BufferAddress,
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Transform>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -82,6 +142,18 @@ impl super::Pipeline for RenderPipeline {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(&sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: species_colors_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: transform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&bind.obstacle_view),
+                },
             ],
         });
 
@@ -123,14 +195,36 @@ impl super::Pipeline for RenderPipeline {
             index_buffer,
             vertex_buffer,
             index_count: index_data.len(),
+            species_colors_buffer,
+            transform_buffer,
+            width: bind.width,
+            height: bind.height,
+            fit: settings.fit,
+            viewport: bind.viewport,
         }
     }
 
-    fn update(&mut self, _queue: &wgpu::Queue, _update: &Self::Update) {}
+    fn update(&mut self, queue: &wgpu::Queue, update: &Self::Update) {
+        self.viewport = *update;
+        self.write_transform(queue);
+    }
+
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &crate::settings::AppSettings) {
+        queue.write_buffer(
+            &self.species_colors_buffer,
+            0,
+            bytemuck::bytes_of(&SpeciesColors::from(settings)),
+        );
+
+        if settings.fit != self.fit {
+            self.fit = settings.fit;
+            self.write_transform(queue);
+        }
+    }
 
     fn execute(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::TextureView) {
         let color_attachments = [Some(wgpu::RenderPassColorAttachment {
-            view: &frame,
+            view: frame,
             resolve_target: None,
             ops: wgpu::Operations {
                 load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -146,7 +240,7 @@ impl super::Pipeline for RenderPipeline {
 
         encoder.push_debug_group("Render Pipeline");
         {
-            let mut render_pass = encoder.begin_render_pass(&&render_pass_descriptor);
+            let mut render_pass = encoder.begin_render_pass(&render_pass_descriptor);
             render_pass.set_pipeline(&self.pipeline);
             render_pass.set_bind_group(0, &self.bind_group, &[]);
             render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
@@ -157,6 +251,13 @@ impl super::Pipeline for RenderPipeline {
     }
 }
 
+impl RenderPipeline {
+    fn write_transform(&self, queue: &wgpu::Queue) {
+        let transform = Transform::new(self.fit, self.width, self.height, &self.viewport);
+        queue.write_buffer(&self.transform_buffer, 0, bytemuck::bytes_of(&transform));
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 struct Vertex {
@@ -164,6 +265,76 @@ struct Vertex {
     tex_coord: [f32; 2],
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SpeciesColors {
+    colors: [[f32; 4]; MAX_SPECIES],
+}
+
+impl From<&crate::settings::AppSettings> for SpeciesColors {
+    fn from(settings: &crate::settings::AppSettings) -> Self {
+        let mut colors = [[0.0; 4]; MAX_SPECIES];
+
+        for (color, species) in colors.iter_mut().zip(&settings.species) {
+            let [r, g, b] = species.color;
+            *color = [r, g, b, 1.0];
+        }
+
+        SpeciesColors { colors }
+    }
+}
+
+/// Scales the full-screen quad so the simulation keeps its aspect ratio.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Transform {
+    scale: [f32; 2],
+}
+
+impl Transform {
+    fn new(fit: Fit, width: u32, height: u32, viewport: &Viewport) -> Self {
+        // Greater than one when the simulation is wider than the window.
+        let ratio = (width as f32 / height as f32)
+            / (viewport.width.max(1) as f32 / viewport.height.max(1) as f32);
+
+        let scale = match fit {
+            Fit::Stretch => [1.0, 1.0],
+            Fit::Letterbox if ratio > 1.0 => [1.0, 1.0 / ratio],
+            Fit::Letterbox => [ratio, 1.0],
+            Fit::Crop if ratio > 1.0 => [ratio, 1.0],
+            Fit::Crop => [1.0, 1.0 / ratio],
+        };
+
+        Transform { scale }
+    }
+}
+
+/// Maps a position in physical pixels of the window to pixels of the
+/// simulation, undoing the fit transform. `None` outside the drawn
+/// simulation, such as on the letterbox bars.
+pub fn window_to_simulation(
+    settings: &crate::settings::AppSettings,
+    viewport: &Viewport,
+    position: [f32; 2],
+) -> Option<[f32; 2]> {
+    let Transform { scale } =
+        Transform::new(settings.fit, settings.width, settings.height, viewport);
+
+    // Back to the corners of the quad. The window's y axis points down, the
+    // quad's up, with row zero of the simulation at the bottom.
+    let x = (2.0 * position[0] / viewport.width.max(1) as f32 - 1.0) / scale[0];
+    let y = (1.0 - 2.0 * position[1] / viewport.height.max(1) as f32) / scale[1];
+
+    if x.abs() > 1.0 || y.abs() > 1.0 {
+        return None;
+    }
+
+    Some([
+        (x + 1.0) * 0.5 * settings.width as f32,
+        (y + 1.0) * 0.5 * settings.height as f32,
+    ])
+}
+
 fn vertex(pos: [i8; 2], tex: [i8; 2]) -> Vertex {
     Vertex {
         pos: [pos[0] as f32, pos[1] as f32],
@@ -186,7 +357,17 @@ fn create_vertices() -> (Vec<Vertex>, Vec<u16>) {
 
 pub struct RenderSettings {
     pub format: wgpu::TextureFormat,
+    pub texture_view: wgpu::TextureView,
+    /// The obstacle mask, drawn over the trails.
+    pub obstacle_view: wgpu::TextureView,
+    pub width: u32,
+    pub height: u32,
+    pub viewport: Viewport,
+}
+
+/// Size of the surface the simulation is presented on.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
     pub width: u32,
     pub height: u32,
-    pub texture_view: wgpu::TextureView,
 }
@@ -0,0 +1,222 @@
+use std::{borrow::Cow, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{agent_buffers::AgentBuffers, shader_pipeline::Agent};
+
+use super::agent_ranges::{AgentRange, AgentRanges};
+
+const RESAMPLE_SIZE: f32 = 8.0;
+const AGENTS_PER_GROUP: u32 = 64;
+
+/// Carries a running simulation over to a new size: the trail map is
+/// resampled into the new texture and agent positions are scaled to match.
+pub struct ResamplePipeline {
+    trail_pipeline: wgpu::ComputePipeline,
+    agents_pipeline: wgpu::ComputePipeline,
+    /// One for each agent buffer. The trail pass runs with the first.
+    bind_groups: Vec<wgpu::BindGroup>,
+    agent_ranges: AgentRanges,
+    workgroup_count_x: u32,
+    workgroup_count_y: u32,
+}
+
+impl super::Pipeline for ResamplePipeline {
+    type Bind = ResampleSetup;
+    type Update = ();
+
+    fn new(
+        device: &wgpu::Device,
+        _settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("slime::shader::resample"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../../shaders/resample.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("slime::shader::resample_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Globals>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba16Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Agent>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<AgentRange>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let globals = Globals {
+            old_width: bind.old_width,
+            old_height: bind.old_height,
+            new_width: bind.new_width,
+            new_height: bind.new_height,
+        };
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::resample_globals_buffer"),
+            contents: bytemuck::bytes_of(&globals),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let agent_ranges =
+            AgentRanges::new(device, &bind.agents, bind.num_agents, AGENTS_PER_GROUP);
+
+        let bind_groups = bind
+            .agents
+            .chunks()
+            .zip(agent_ranges.buffers())
+            .map(|(chunk, range_buffer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("slime::shader::resample::bind_group"),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: globals_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&bind.old_trail_map),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&bind.new_trail_map),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: chunk.buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: range_buffer.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("slime::shader::resample_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let trail_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("slime::shader::resample_trail_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "resample_trail",
+        });
+
+        let agents_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("slime::shader::rescale_agents_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "rescale_agents",
+        });
+
+        Self {
+            trail_pipeline,
+            agents_pipeline,
+            bind_groups,
+            agent_ranges,
+            workgroup_count_x: (bind.new_width as f32 / RESAMPLE_SIZE).ceil() as u32,
+            workgroup_count_y: (bind.new_height as f32 / RESAMPLE_SIZE).ceil() as u32,
+        }
+    }
+
+    fn update(&mut self, _queue: &wgpu::Queue, _update: &Self::Update) {}
+
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, _frame: &wgpu::TextureView) {
+        encoder.push_debug_group("resample simulation");
+        {
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_bind_group(0, &self.bind_groups[0], &[]);
+
+            compute_pass.set_pipeline(&self.trail_pipeline);
+            compute_pass.dispatch_workgroups(self.workgroup_count_x, self.workgroup_count_y, 1);
+
+            compute_pass.set_pipeline(&self.agents_pipeline);
+            self.agent_ranges
+                .dispatch(&mut compute_pass, &self.bind_groups);
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+pub struct ResampleSetup {
+    pub old_width: u32,
+    pub old_height: u32,
+    pub new_width: u32,
+    pub new_height: u32,
+    pub old_trail_map: wgpu::TextureView,
+    pub new_trail_map: wgpu::TextureView,
+    pub agents: Rc<AgentBuffers>,
+    /// Slots of `agents` in use.
+    pub num_agents: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Globals {
+    old_width: u32,
+    old_height: u32,
+    new_width: u32,
+    new_height: u32,
+}
@@ -1,24 +1,38 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, rc::Rc};
 
 use bytemuck::{Pod, Zeroable};
 use wgpu::util::DeviceExt;
 
-use crate::shader_pipeline::Agent;
+use crate::{
+    agent_buffers::AgentBuffers, brush::BrushBuffer, settings::MAX_SPECIES, shader_pipeline::Agent,
+};
 
-const PARTICLES_PER_GROUP: usize = 64;
+use super::agent_ranges::{AgentRange, AgentRanges};
+
+const PARTICLES_PER_GROUP: u32 = 64;
 
 pub struct SlimeSimPipeline {
     pipeline: wgpu::ComputePipeline,
+    globals: Globals,
+    globals_buffer: wgpu::Buffer,
+    species_buffer: wgpu::Buffer,
     time_buffer: wgpu::Buffer,
-    work_group_count: u32,
-    bind_group: wgpu::BindGroup,
+    attractor_buffer: wgpu::Buffer,
+    eraser_buffer: wgpu::Buffer,
+    agent_ranges: AgentRanges,
+    /// One for each agent buffer.
+    bind_groups: Vec<wgpu::BindGroup>,
 }
 
 impl super::Pipeline for SlimeSimPipeline {
     type Bind = SlimeSimSetup;
     type Update = TimeBuffer;
 
-    fn new(device: &wgpu::Device, settings: &crate::app::AppSettings, bind: &Self::Bind) -> Self {
+    fn new(
+        device: &wgpu::Device,
+        settings: &crate::settings::AppSettings,
+        bind: &Self::Bind,
+    ) -> Self {
         let slime_sim_compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("slime::shader::slime_sim_compute"),
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
@@ -61,7 +75,7 @@ impl super::Pipeline for SlimeSimPipeline {
                             ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
                             min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<
-                                SpeciesSetting,
+                                SpeciesSettings,
                             >()
                                 as wgpu::BufferAddress),
                         },
@@ -74,8 +88,7 @@ impl super::Pipeline for SlimeSimPipeline {
                             ty: wgpu::BufferBindingType::Storage { read_only: false },
                             has_dynamic_offset: false,
                             min_binding_size: wgpu::BufferSize::new(
-                                (std::mem::size_of::<Agent>() * settings.num_agents)
-                                    as wgpu::BufferAddress,
+                                std::mem::size_of::<Agent>() as wgpu::BufferAddress
                             ),
                         },
                         count: None,
@@ -93,25 +106,84 @@ impl super::Pipeline for SlimeSimPipeline {
                     wgpu::BindGroupLayoutEntry {
                         binding: 5,
                         visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: wgpu::TextureFormat::Rgba16Float,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
                             view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<BrushBuffer>() as wgpu::BufferAddress,
+                            ),
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<BrushBuffer>() as wgpu::BufferAddress,
+                            ),
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<AgentRange>() as wgpu::BufferAddress,
+                            ),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<u32>() as wgpu::BufferAddress
+                            ),
                         },
                         count: None,
                     },
                 ],
             });
 
-        let globals_data = Globals {
-            trail_weight: settings.trail_weight,
+        let globals = Globals {
             width: bind.width,
             height: bind.height,
+            seed: bind.seed,
+            boundary: settings.boundary.shader_index(),
         };
 
-        let globals_data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("slime::shader::simulation_parameter_buffer"),
-            contents: bytemuck::bytes_of(&globals_data),
+            contents: bytemuck::bytes_of(&globals),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
@@ -126,14 +198,19 @@ impl super::Pipeline for SlimeSimPipeline {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let species_settings = SpeciesSetting {
-            move_speed: settings.move_speed,
-            turn_speed: settings.turn_speed,
+        let attractor_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::attractor_buffer"),
+            contents: bytemuck::bytes_of(&BrushBuffer::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let eraser_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::eraser_buffer"),
+            contents: bytemuck::bytes_of(&BrushBuffer::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
 
-            sensor_angle_degrees: settings.sensor_angle_degrees,
-            sensor_offset_dst: settings.sensor_offset_dst,
-            sensor_size: settings.sensor_size,
-        };
+        let species_settings = SpeciesSettings::from(settings);
 
         let species_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("slime::shader::species_buffer"),
@@ -141,41 +218,68 @@ impl super::Pipeline for SlimeSimPipeline {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &slime_sim_compute_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: globals_data_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: time_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: species_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: bind.binding.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: wgpu::BindingResource::TextureView(&bind.trail_map_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 5,
-                    resource: wgpu::BindingResource::TextureView(
-                        &bind.trail_map_write_texture_view,
-                    ),
-                },
-            ],
-            label: Some("slime::shader::slime_sim::bind_group"),
-        });
+        let agent_ranges =
+            AgentRanges::new(device, &bind.agents, bind.num_agents, PARTICLES_PER_GROUP);
 
-        let work_group_count =
-            ((settings.num_agents as f32) / (PARTICLES_PER_GROUP as f32)).ceil() as u32;
+        let bind_groups = bind
+            .agents
+            .chunks()
+            .zip(agent_ranges.buffers())
+            .map(|(chunk, range_buffer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &slime_sim_compute_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: globals_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: time_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: species_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: chunk.buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: wgpu::BindingResource::TextureView(
+                                &bind.trail_map_texture_view,
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 5,
+                            resource: bind.deposit_counts.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 6,
+                            resource: wgpu::BindingResource::TextureView(&bind.obstacle_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 7,
+                            resource: attractor_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 8,
+                            resource: eraser_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 9,
+                            resource: range_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 10,
+                            resource: bind.deleted.as_entire_binding(),
+                        },
+                    ],
+                    label: Some("slime::shader::slime_sim::bind_group"),
+                })
+            })
+            .collect();
 
         let slime_sim_compute_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -193,9 +297,14 @@ impl super::Pipeline for SlimeSimPipeline {
 
         Self {
             pipeline,
+            globals,
+            globals_buffer,
+            species_buffer,
             time_buffer,
-            work_group_count,
-            bind_group,
+            attractor_buffer,
+            eraser_buffer,
+            agent_ranges,
+            bind_groups,
         }
     }
 
@@ -203,25 +312,56 @@ impl super::Pipeline for SlimeSimPipeline {
         queue.write_buffer(&self.time_buffer, 0, bytemuck::bytes_of(update));
     }
 
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &crate::settings::AppSettings) {
+        self.globals.boundary = settings.boundary.shader_index();
+        queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&self.globals));
+
+        let species_settings = SpeciesSettings::from(settings);
+        queue.write_buffer(
+            &self.species_buffer,
+            0,
+            bytemuck::bytes_of(&species_settings),
+        );
+    }
+
     fn execute(&self, encoder: &mut wgpu::CommandEncoder, _frame: &wgpu::TextureView) {
         encoder.push_debug_group("compute boid movement");
         {
             let mut compute_pass =
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
             compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &self.bind_group, &[]);
-            compute_pass.dispatch_workgroups(self.work_group_count, 1, 1);
+            self.agent_ranges
+                .dispatch(&mut compute_pass, &self.bind_groups);
         }
         encoder.pop_debug_group();
     }
 }
 
+impl SlimeSimPipeline {
+    /// Moves the cursor attractor, or turns it off with a zero amount.
+    pub fn set_attractor(&self, queue: &wgpu::Queue, attractor: &BrushBuffer) {
+        queue.write_buffer(&self.attractor_buffer, 0, bytemuck::bytes_of(attractor));
+    }
+
+    /// Moves the circle agents are deleted in, or turns it off with a zero
+    /// amount.
+    pub fn set_eraser(&self, queue: &wgpu::Queue, eraser: &BrushBuffer) {
+        queue.write_buffer(&self.eraser_buffer, 0, bytemuck::bytes_of(eraser));
+    }
+
+    /// Runs the agents in the first `num_agents` slots of the buffers.
+    pub fn set_agent_count(&mut self, queue: &wgpu::Queue, num_agents: u32) {
+        self.agent_ranges.set_agent_count(queue, num_agents);
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 struct Globals {
-    trail_weight: f32,
     width: u32,
     height: u32,
+    seed: u32,
+    boundary: u32,
 }
 
 #[repr(C)]
@@ -239,16 +379,49 @@ struct SpeciesSetting {
     sensor_angle_degrees: f32,
     sensor_offset_dst: f32,
     sensor_size: i32,
+    _padding: [i32; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SpeciesSettings {
+    species: [SpeciesSetting; MAX_SPECIES],
+}
+
+impl From<&crate::settings::AppSettings> for SpeciesSettings {
+    fn from(settings: &crate::settings::AppSettings) -> Self {
+        let mut species = [SpeciesSetting::zeroed(); MAX_SPECIES];
+
+        for (setting, species_settings) in species.iter_mut().zip(&settings.species) {
+            *setting = SpeciesSetting {
+                move_speed: species_settings.move_speed,
+                turn_speed: species_settings.turn_speed,
+
+                sensor_angle_degrees: species_settings.sensor_angle_degrees,
+                sensor_offset_dst: species_settings.sensor_offset_dst,
+                sensor_size: species_settings.sensor_size,
+                _padding: [0; 3],
+            };
+        }
+
+        SpeciesSettings { species }
+    }
 }
 
 #[derive(Debug)]
 pub struct SlimeSimSetup {
     pub width: u32,
     pub height: u32,
-    pub format: wgpu::TextureFormat,
-    pub binding: wgpu::Buffer,
+    pub agents: Rc<AgentBuffers>,
     pub trail_map_texture_view: wgpu::TextureView,
-    pub trail_map_write_texture_view: wgpu::TextureView,
+    /// Per texel and species agent counts, added to by the simulation and
+    /// taken by the deposit pass.
+    pub deposit_counts: Rc<wgpu::Buffer>,
+    /// How many agents the eraser has deleted, counted up by the simulation.
+    pub deleted: Rc<wgpu::Buffer>,
+    pub obstacle_view: wgpu::TextureView,
     pub display_texture_view: wgpu::TextureView,
+    /// Slots of `agents` in use. The buffers may hold more.
     pub num_agents: u32,
+    pub seed: u32,
 }
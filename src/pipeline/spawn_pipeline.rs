@@ -0,0 +1,208 @@
+use std::{borrow::Cow, rc::Rc};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    agent_buffers::AgentBuffers,
+    settings::{AppSettings, Facing, SpawnMode},
+    shader_pipeline::{self, Agent},
+};
+
+use super::agent_ranges::{AgentRange, AgentRanges};
+
+const AGENTS_PER_GROUP: u32 = 64;
+
+/// Places agents by the spawn pattern in the agent buffers, so new runs and
+/// grown populations never pass through the CPU.
+pub struct SpawnPipeline {
+    pipeline: wgpu::ComputePipeline,
+    spawn_buffer: wgpu::Buffer,
+    agent_ranges: AgentRanges,
+    /// One for each agent buffer.
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl super::Pipeline for SpawnPipeline {
+    type Bind = SpawnSetup;
+    type Update = SpawnBuffer;
+
+    fn new(device: &wgpu::Device, _settings: &AppSettings, bind: &Self::Bind) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("slime::shader::spawn_agents"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
+                "../../shaders/spawn_agents.wgsl"
+            ))),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("slime::shader::spawn_agents_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<SpawnBuffer>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Agent>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<AgentRange>() as wgpu::BufferAddress
+                        ),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let spawn_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::spawn_buffer"),
+            contents: bytemuck::bytes_of(&SpawnBuffer::zeroed()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let agent_ranges = AgentRanges::new(device, &bind.agents, 0, AGENTS_PER_GROUP);
+
+        let bind_groups = bind
+            .agents
+            .chunks()
+            .zip(agent_ranges.buffers())
+            .map(|(chunk, range_buffer)| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("slime::shader::spawn_agents::bind_group"),
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: spawn_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: chunk.buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: range_buffer.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("slime::shader::spawn_agents_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("slime::shader::spawn_agents_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        Self {
+            pipeline,
+            spawn_buffer,
+            agent_ranges,
+            bind_groups,
+        }
+    }
+
+    /// Sets the agents the next run places. Buffers past the last of them
+    /// are skipped; earlier ones are dispatched and left alone by the shader.
+    fn update(&mut self, queue: &wgpu::Queue, update: &Self::Update) {
+        queue.write_buffer(&self.spawn_buffer, 0, bytemuck::bytes_of(update));
+        self.agent_ranges
+            .set_agent_count(queue, update.first_index + update.num_agents);
+    }
+
+    fn execute(&self, encoder: &mut wgpu::CommandEncoder, _frame: &wgpu::TextureView) {
+        encoder.push_debug_group("spawn agents");
+        {
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(&self.pipeline);
+            self.agent_ranges
+                .dispatch(&mut compute_pass, &self.bind_groups);
+        }
+        encoder.pop_debug_group();
+    }
+}
+
+pub struct SpawnSetup {
+    pub agents: Rc<AgentBuffers>,
+}
+
+/// Agents to place by the spawn pattern, as `spawn_agents.wgsl` reads them.
+/// `cpu::spawned_agents` places the same ones.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub struct SpawnBuffer {
+    pub seed: u32,
+    pub first_index: u32,
+    pub num_agents: u32,
+    pub num_species: u32,
+    pub mode: u32,
+    pub inward: u32,
+    pub width: u32,
+    pub height: u32,
+    pub extent: [f32; 2],
+    _padding: [f32; 2],
+}
+
+impl SpawnBuffer {
+    /// `count` agents from population index `first_index` on, for a run with
+    /// `seed` at `step`. Agents placed at different steps differ.
+    pub fn new(
+        settings: &AppSettings,
+        seed: u64,
+        step: u32,
+        first_index: usize,
+        count: usize,
+    ) -> Self {
+        let (mode, extent, inward) = match settings.spawn {
+            SpawnMode::Point => (0, [0.0; 2], false),
+            SpawnMode::Random => (1, [0.0; 2], false),
+            SpawnMode::Circle { radius } => (2, [radius, 0.0], false),
+            SpawnMode::Ring { radius, facing } => (3, [radius, 0.0], facing == Facing::Inward),
+            SpawnMode::Rect { width, height } => (4, [width, height], false),
+        };
+
+        Self {
+            seed: shader_pipeline::shader_seed(seed) ^ step.wrapping_mul(0x9e37_79b9),
+            first_index: first_index as u32,
+            num_agents: count as u32,
+            num_species: settings.species.len() as u32,
+            mode,
+            inward: inward as u32,
+            width: settings.width,
+            height: settings.height,
+            extent,
+            _padding: [0.0; 2],
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::app::{App, AppSettings};
+use crate::{app::App, settings::AppSettings};
 
 #[cfg(target_arch = "wasm32")]
 pub fn run_app<F>(settings: AppSettings, runner: F)
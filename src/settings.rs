@@ -0,0 +1,999 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Each species owns one channel of the RGBA trail map.
+pub const MAX_SPECIES: usize = 4;
+
+/// The most agents a run may have.
+pub const MAX_AGENTS: usize = 100_000_000;
+
+/// The widest sensor, in texels either side of its center. Every agent reads
+/// `(2 * sensor_size + 1)²` texels for each of its three sensors a step.
+pub const MAX_SENSOR_SIZE: i32 = 10;
+
+const SPECIES_COLORS: [[f32; 3]; MAX_SPECIES] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 0.3, 0.2],
+    [0.2, 1.0, 0.4],
+    [0.3, 0.5, 1.0],
+];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppSettings {
+    /// Simulation resolution, independent of the window size.
+    pub width: u32,
+    pub height: u32,
+    pub fit: Fit,
+    /// Rebuild the simulation at the window size whenever the window is
+    /// resized, carrying over the trail map and agents.
+    pub resize_with_window: bool,
+    pub trail_weight: f32,
+    pub num_agents: usize,
+    /// Simulation steps per second of real time.
+    pub sim_rate: f32,
+    /// Multiplies real time before it is turned into steps, for slow motion
+    /// and fast-forward.
+    pub time_scale: f32,
+    /// Steps run at most per frame. Time beyond that is dropped rather than
+    /// caught up, so a slow frame cannot snowball.
+    pub max_steps_per_frame: usize,
+    /// Steps taken at once by the frame-advance control while paused.
+    pub frame_advance: usize,
+    /// Seeds agent spawning and the steering noise. With the same seed,
+    /// settings and adapter, runs repeat step for step. A random seed is
+    /// picked and logged when unset.
+    pub seed: Option<u64>,
+    pub species: Vec<SpeciesSettings>,
+    pub boundary: BoundaryMode,
+    pub spawn: SpawnMode,
+    pub init_image: Option<InitImage>,
+    pub obstacles: Option<ObstacleImage>,
+    /// Sources that keep adding attractant to the trail map, for agents to
+    /// form networks between.
+    pub food: Vec<FoodSource>,
+    pub brush: BrushSettings,
+    pub decay_rate: f32,
+    pub diffuse_rate: f32,
+    pub agents_only: bool,
+    /// Accepted from settings files written before the simulation followed
+    /// real time, and folded into `time_scale` by `load`.
+    #[serde(skip_serializing)]
+    pub steps_per_frame: Option<usize>,
+    /// Species fields from settings files written before there were several
+    /// species, applied to every species by `load`.
+    #[serde(skip_serializing)]
+    pub move_speed: Option<f32>,
+    #[serde(skip_serializing)]
+    pub turn_speed: Option<f32>,
+    #[serde(skip_serializing)]
+    pub sensor_angle_degrees: Option<f32>,
+    #[serde(skip_serializing)]
+    pub sensor_offset_dst: Option<f32>,
+    #[serde(skip_serializing)]
+    pub sensor_size: Option<i32>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            fit: Fit::Letterbox,
+            resize_with_window: false,
+
+            num_agents: 750_000,
+            sim_rate: 60.0,
+            time_scale: 1.0,
+            max_steps_per_frame: 8,
+            frame_advance: 10,
+            seed: None,
+
+            species: vec![SpeciesSettings::default()],
+            boundary: BoundaryMode::Clamp,
+            spawn: SpawnMode::Point,
+            init_image: None,
+            obstacles: None,
+            food: Vec::new(),
+            brush: BrushSettings::default(),
+
+            trail_weight: 2.0,
+            decay_rate: 0.75,
+            diffuse_rate: 5.0,
+
+            agents_only: false,
+            steps_per_frame: None,
+            move_speed: None,
+            turn_speed: None,
+            sensor_angle_degrees: None,
+            sensor_offset_dst: None,
+            sensor_size: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeciesSettings {
+    pub move_speed: f32,
+    pub turn_speed: f32,
+    pub sensor_angle_degrees: f32,
+    pub sensor_offset_dst: f32,
+    pub sensor_size: i32,
+    pub color: [f32; 3],
+}
+
+impl Default for SpeciesSettings {
+    fn default() -> Self {
+        Self {
+            move_speed: 50.0,
+            turn_speed: -3.0,
+
+            sensor_angle_degrees: 112.0,
+            sensor_offset_dst: 20.0,
+            sensor_size: 1,
+
+            color: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+/// How the simulation is shown when its aspect ratio differs from the
+/// window's.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Fit {
+    /// Show the whole simulation, with black bars around it.
+    #[default]
+    Letterbox,
+    /// Fill the window, cutting off the edges of the simulation.
+    Crop,
+    /// Fill the window, distorting the simulation.
+    Stretch,
+}
+
+impl Fit {
+    pub fn name(self) -> &'static str {
+        match self {
+            Fit::Letterbox => "letterbox",
+            Fit::Crop => "crop",
+            Fit::Stretch => "stretch",
+        }
+    }
+}
+
+/// What happens to agents that move off the edge of the map, and how
+/// sensing and diffusion treat the edges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Stop at the edge and turn in a random direction.
+    #[default]
+    Clamp,
+    /// Wrap around to the opposite edge. Sensors and diffusion wrap too, so
+    /// the trail map tiles seamlessly.
+    Torus,
+    /// Bounce off the edge like a mirror.
+    Reflect,
+    /// Reappear at a random position, facing a random direction.
+    RespawnRandom,
+    /// Reappear at the center, facing a random direction.
+    RespawnCenter,
+}
+
+impl BoundaryMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            BoundaryMode::Clamp => "clamp",
+            BoundaryMode::Torus => "torus",
+            BoundaryMode::Reflect => "reflect",
+            BoundaryMode::RespawnRandom => "respawn random",
+            BoundaryMode::RespawnCenter => "respawn center",
+        }
+    }
+
+    /// The matching `BOUNDARY_*` constant in the shaders.
+    pub fn shader_index(self) -> u32 {
+        match self {
+            BoundaryMode::Clamp => 0,
+            BoundaryMode::Torus => 1,
+            BoundaryMode::Reflect => 2,
+            BoundaryMode::RespawnRandom => 3,
+            BoundaryMode::RespawnCenter => 4,
+        }
+    }
+}
+
+/// Where agents start and which way they face. Distances are in pixels of
+/// the simulation, measured from its center.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SpawnMode {
+    /// Every agent at the center, facing a random direction.
+    Point,
+    /// Uniformly over the whole map, facing a random direction.
+    Random,
+    /// Filled disc, every agent facing the center.
+    Circle { radius: f32 },
+    /// On a circle's edge, facing towards or away from the center.
+    Ring { radius: f32, facing: Facing },
+    /// Uniformly inside a centered rectangle, facing a random direction.
+    Rect { width: f32, height: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Facing {
+    Inward,
+    Outward,
+}
+
+/// A PNG, stretched to the simulation size, that drives the initial state.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InitImage {
+    pub path: PathBuf,
+    /// Spawn agents with a probability proportional to pixel brightness,
+    /// replacing `spawn`.
+    #[serde(default = "default_true")]
+    pub spawn_agents: bool,
+    /// Use the image's R, G, B and A channels for species 1 to 4 instead of
+    /// its luminance.
+    #[serde(default)]
+    pub per_species: bool,
+    /// Pre-fill the trail map from the image.
+    #[serde(default)]
+    pub seed_trails: bool,
+}
+
+impl InitImage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            spawn_agents: true,
+            per_species: false,
+            seed_trails: false,
+        }
+    }
+}
+
+/// A mask of walls that agents cannot enter, sense as strongly repellent and
+/// that trails do not diffuse through. It is resampled to the simulation
+/// size and only read when a run starts; snapshots keep their own copy.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObstacleImage {
+    pub path: PathBuf,
+    /// Treat dark pixels as walls instead of bright ones, for mazes drawn in
+    /// black on white.
+    #[serde(default)]
+    pub invert: bool,
+}
+
+impl ObstacleImage {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            invert: false,
+        }
+    }
+}
+
+/// Adds `strength` of attractant per second of simulated time to the trail
+/// channel of `species`, or of every species when unset.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FoodSource {
+    pub shape: FoodShape,
+    pub strength: f32,
+    #[serde(default)]
+    pub species: Option<usize>,
+}
+
+impl FoodSource {
+    pub fn validate(&self, num_species: usize) -> Result<(), SettingsError> {
+        if !self.strength.is_finite() || self.strength < 0.0 {
+            return Err(SettingsError::invalid(
+                "food.strength",
+                "must be a non-negative number",
+            ));
+        }
+
+        if let Some(species) = self.species {
+            if species >= num_species {
+                return Err(SettingsError::invalid(
+                    "food.species",
+                    format!("must be below the number of species ({})", num_species),
+                ));
+            }
+        }
+
+        match &self.shape {
+            FoodShape::Point { position } | FoodShape::Circle { position, .. }
+                if !position.iter().all(|value| value.is_finite()) =>
+            {
+                Err(SettingsError::invalid(
+                    "food.shape.position",
+                    "must be finite numbers",
+                ))
+            }
+            FoodShape::Circle { radius, .. } if !radius.is_finite() || *radius < 0.0 => Err(
+                SettingsError::invalid("food.shape.radius", "must be a non-negative number"),
+            ),
+            FoodShape::Image { path } if !path.is_file() => Err(SettingsError::invalid(
+                "food.shape.path",
+                format!("{} is not a file", path.display()),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The mouse brush. Left-drag paints attractant into the trail map and
+/// right-drag erases it; with Shift held, the cursor attracts or repels
+/// agents instead, and with Ctrl held it spawns or deletes them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrushSettings {
+    /// In pixels of the simulation.
+    pub radius: f32,
+    /// Scales how fast the brush paints and how hard it pulls.
+    pub strength: f32,
+    /// Agents spawned per step while spawning.
+    pub spawn_count: usize,
+    pub spawn_heading: SpawnHeading,
+}
+
+impl Default for BrushSettings {
+    fn default() -> Self {
+        Self {
+            radius: 30.0,
+            strength: 1.0,
+            spawn_count: 100,
+            spawn_heading: SpawnHeading::Random,
+        }
+    }
+}
+
+/// Which way agents from the spawn brush face.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpawnHeading {
+    #[default]
+    Random,
+    /// Away from the cursor.
+    Outward,
+}
+
+/// Where a food source deposits. Positions are in pixels of the simulation,
+/// measured from its center.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FoodShape {
+    /// The single pixel at `position`.
+    Point { position: [f32; 2] },
+    /// A filled disc.
+    Circle { position: [f32; 2], radius: f32 },
+    /// Every pixel of a PNG stretched over the map, scaled by its
+    /// brightness.
+    Image { path: PathBuf },
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl SpawnMode {
+    /// Radius used when a pattern is picked without giving its extent.
+    pub const DEFAULT_RADIUS: f32 = 200.0;
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpawnMode::Point => "point",
+            SpawnMode::Random => "random",
+            SpawnMode::Circle { .. } => "circle",
+            SpawnMode::Ring {
+                facing: Facing::Inward,
+                ..
+            } => "ring (inward)",
+            SpawnMode::Ring {
+                facing: Facing::Outward,
+                ..
+            } => "ring (outward)",
+            SpawnMode::Rect { .. } => "rect",
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let extents: &[(&'static str, f32)] = match self {
+            SpawnMode::Point | SpawnMode::Random => &[],
+            SpawnMode::Circle { radius } | SpawnMode::Ring { radius, .. } => {
+                &[("spawn.radius", *radius)]
+            }
+            SpawnMode::Rect { width, height } => {
+                &[("spawn.width", *width), ("spawn.height", *height)]
+            }
+        };
+
+        for &(field, value) in extents {
+            if !value.is_finite() || value < 0.0 {
+                return Err(SettingsError::invalid(
+                    field,
+                    "must be a non-negative number",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl AppSettings {
+    /// `settings`, except for the texture size, agent count and number of
+    /// species, which are kept from `self`. The size and count change through
+    /// methods of their own, `resize_simulation` and `set_agent_count`, and
+    /// the species only with a restart.
+    pub fn updated_with(&self, settings: &AppSettings) -> AppSettings {
+        let species = if settings.species.len() == self.species.len() {
+            settings.species.clone()
+        } else {
+            self.species.clone()
+        };
+
+        AppSettings {
+            width: self.width,
+            height: self.height,
+            num_agents: self.num_agents,
+            species,
+            ..settings.clone()
+        }
+    }
+
+    /// Adds or removes species at the end of the list. New species copy the
+    /// last one with a color of their own, so they remain distinguishable.
+    pub fn resize_species(&mut self, count: usize) {
+        let count = count.clamp(1, MAX_SPECIES);
+        let template = self.species.last().cloned().unwrap_or_default();
+
+        while self.species.len() < count {
+            let color = SPECIES_COLORS[self.species.len()];
+            self.species.push(SpeciesSettings {
+                color,
+                ..template.clone()
+            });
+        }
+
+        self.species.truncate(count);
+    }
+
+    /// Reads settings from a TOML, JSON or RON file, picked by extension.
+    /// Fields missing from the file keep their default values.
+    pub fn load(path: &Path) -> Result<Self, SettingsError> {
+        let contents = fs::read_to_string(path).map_err(|err| SettingsError::Io {
+            path: path.to_owned(),
+            source: err,
+        })?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        let parsed = match extension.as_deref() {
+            Some("toml") => toml::from_str(&contents).map_err(|err| err.to_string()),
+            Some("json") => serde_json::from_str(&contents).map_err(|err| err.to_string()),
+            Some("ron") => ron::from_str(&contents).map_err(|err| err.to_string()),
+            _ => return Err(SettingsError::UnsupportedFormat(path.to_owned())),
+        };
+
+        let mut settings: AppSettings = parsed.map_err(|message| SettingsError::Parse {
+            path: path.to_owned(),
+            message,
+        })?;
+        settings.fold_steps_per_frame();
+        settings.fold_species_fields();
+
+        Ok(settings)
+    }
+
+    /// Moves species fields set at the top level, as before there were
+    /// several species, onto every species.
+    fn fold_species_fields(&mut self) {
+        macro_rules! fold {
+            ($($field:ident),* $(,)?) => {
+                $(
+                    if let Some(value) = self.$field.take() {
+                        log::warn!(
+                            "Top-level `{}` is deprecated, set it under [[species]]",
+                            stringify!($field)
+                        );
+
+                        for species in &mut self.species {
+                            species.$field = value;
+                        }
+                    }
+                )*
+            };
+        }
+
+        fold!(
+            move_speed,
+            turn_speed,
+            sensor_angle_degrees,
+            sensor_offset_dst,
+            sensor_size,
+        );
+    }
+
+    /// Turns the deprecated `steps_per_frame` into the same number of steps
+    /// per frame at the default `sim_rate`, by scaling `time_scale` and
+    /// raising `max_steps_per_frame` to match.
+    fn fold_steps_per_frame(&mut self) {
+        if let Some(steps) = self.steps_per_frame.take() {
+            log::warn!(
+                "`steps_per_frame` is deprecated, use `time_scale` and `max_steps_per_frame`"
+            );
+
+            self.time_scale *= steps as f32;
+            self.max_steps_per_frame = self.max_steps_per_frame.max(steps);
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if self.width == 0 {
+            return Err(SettingsError::invalid("width", "must be greater than zero"));
+        }
+
+        if self.height == 0 {
+            return Err(SettingsError::invalid(
+                "height",
+                "must be greater than zero",
+            ));
+        }
+
+        if self.num_agents == 0 || self.num_agents > MAX_AGENTS {
+            return Err(SettingsError::invalid(
+                "num_agents",
+                format!("must be between 1 and {}", MAX_AGENTS),
+            ));
+        }
+
+        if self.species.is_empty() || self.species.len() > MAX_SPECIES {
+            return Err(SettingsError::invalid(
+                "species",
+                format!("must list between 1 and {} species", MAX_SPECIES),
+            ));
+        }
+
+        for species in &self.species {
+            species.validate()?;
+        }
+
+        self.spawn.validate()?;
+
+        if let Some(image) = &self.init_image {
+            if !image.path.is_file() {
+                return Err(SettingsError::invalid(
+                    "init_image.path",
+                    format!("{} is not a file", image.path.display()),
+                ));
+            }
+        }
+
+        if let Some(image) = &self.obstacles {
+            if !image.path.is_file() {
+                return Err(SettingsError::invalid(
+                    "obstacles.path",
+                    format!("{} is not a file", image.path.display()),
+                ));
+            }
+        }
+
+        for food in &self.food {
+            food.validate(self.species.len())?;
+        }
+
+        if !(self.brush.radius.is_finite() && self.brush.radius > 0.0) {
+            return Err(SettingsError::invalid(
+                "brush.radius",
+                "must be a positive number",
+            ));
+        }
+
+        if !(self.brush.strength.is_finite() && self.brush.strength >= 0.0) {
+            return Err(SettingsError::invalid(
+                "brush.strength",
+                "must be a non-negative number",
+            ));
+        }
+
+        if self.max_steps_per_frame == 0 {
+            return Err(SettingsError::invalid(
+                "max_steps_per_frame",
+                "must be greater than zero",
+            ));
+        }
+
+        if !(self.sim_rate.is_finite() && self.sim_rate > 0.0) {
+            return Err(SettingsError::invalid(
+                "sim_rate",
+                "must be a positive number",
+            ));
+        }
+
+        if !(self.time_scale.is_finite() && self.time_scale >= 0.0) {
+            return Err(SettingsError::invalid(
+                "time_scale",
+                "must be a non-negative number",
+            ));
+        }
+
+        let floats = [
+            ("trail_weight", self.trail_weight),
+            ("decay_rate", self.decay_rate),
+            ("diffuse_rate", self.diffuse_rate),
+        ];
+
+        for (field, value) in floats {
+            if !value.is_finite() {
+                return Err(SettingsError::invalid(field, "must be a finite number"));
+            }
+        }
+
+        if self.decay_rate < 0.0 {
+            return Err(SettingsError::invalid("decay_rate", "must not be negative"));
+        }
+
+        if self.diffuse_rate < 0.0 {
+            return Err(SettingsError::invalid(
+                "diffuse_rate",
+                "must not be negative",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl SpeciesSettings {
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        if !(0..=MAX_SENSOR_SIZE).contains(&self.sensor_size) {
+            return Err(SettingsError::invalid(
+                "sensor_size",
+                format!("must be between 0 and {}", MAX_SENSOR_SIZE),
+            ));
+        }
+
+        let floats = [
+            ("move_speed", self.move_speed),
+            ("turn_speed", self.turn_speed),
+            ("sensor_angle_degrees", self.sensor_angle_degrees),
+            ("sensor_offset_dst", self.sensor_offset_dst),
+        ];
+
+        for (field, value) in floats {
+            if !value.is_finite() {
+                return Err(SettingsError::invalid(field, "must be a finite number"));
+            }
+        }
+
+        if self.color.iter().any(|channel| !channel.is_finite()) {
+            return Err(SettingsError::invalid("color", "must be finite numbers"));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io { path: PathBuf, source: io::Error },
+    Parse { path: PathBuf, message: String },
+    UnsupportedFormat(PathBuf),
+    Invalid { field: &'static str, reason: String },
+}
+
+impl SettingsError {
+    pub fn invalid(field: &'static str, reason: impl Into<String>) -> Self {
+        SettingsError::Invalid {
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io { path, source } => {
+                write!(f, "could not read {}: {}", path.display(), source)
+            }
+            SettingsError::Parse { path, message } => {
+                write!(f, "could not parse {}: {}", path.display(), message)
+            }
+            SettingsError::UnsupportedFormat(path) => write!(
+                f,
+                "unsupported settings format for {} (expected .toml, .json or .ron)",
+                path.display()
+            ),
+            SettingsError::Invalid { field, reason } => {
+                write!(f, "invalid setting `{}`: {}", field, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SettingsError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A path named `name` in a fresh temp directory of its own.
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "slime-test-{}-{}",
+            std::process::id(),
+            name.replace('.', "-")
+        ));
+        fs::create_dir_all(&dir).unwrap();
+
+        dir.join(name)
+    }
+
+    /// Writes `contents` to a file named `name` in a fresh temp directory.
+    pub(crate) fn write_temp(name: &str, contents: &str) -> PathBuf {
+        let path = temp_path(name);
+        fs::write(&path, contents).unwrap();
+
+        path
+    }
+
+    fn expected() -> AppSettings {
+        let mut settings = AppSettings {
+            width: 320,
+            height: 240,
+            num_agents: 1000,
+            spawn: SpawnMode::Circle { radius: 50.0 },
+            ..AppSettings::default()
+        };
+        settings.species[0].move_speed = 20.0;
+
+        settings
+    }
+
+    #[test]
+    fn loads_toml() {
+        let path = write_temp(
+            "settings.toml",
+            r#"
+                width = 320
+                height = 240
+                num_agents = 1000
+                spawn = { mode = "circle", radius = 50.0 }
+
+                [[species]]
+                move_speed = 20.0
+            "#,
+        );
+
+        assert_eq!(AppSettings::load(&path).unwrap(), expected());
+    }
+
+    #[test]
+    fn loads_json() {
+        let path = write_temp(
+            "settings.json",
+            r#"{
+                "width": 320,
+                "height": 240,
+                "num_agents": 1000,
+                "spawn": { "mode": "circle", "radius": 50.0 },
+                "species": [{ "move_speed": 20.0 }]
+            }"#,
+        );
+
+        assert_eq!(AppSettings::load(&path).unwrap(), expected());
+    }
+
+    #[test]
+    fn loads_ron() {
+        let path = write_temp(
+            "settings.ron",
+            r#"(
+                width: 320,
+                height: 240,
+                num_agents: 1000,
+                spawn: (mode: "circle", radius: 50.0),
+                species: [(move_speed: 20.0)],
+            )"#,
+        );
+
+        assert_eq!(AppSettings::load(&path).unwrap(), expected());
+    }
+
+    #[test]
+    fn folds_deprecated_steps_per_frame() {
+        let path = write_temp("old.toml", "num_agents = 1000\nsteps_per_frame = 3");
+        let settings = AppSettings::load(&path).unwrap();
+
+        assert_eq!(settings.time_scale, 3.0);
+        assert_eq!(settings.max_steps_per_frame, 8);
+        assert_eq!(
+            settings,
+            AppSettings {
+                num_agents: 1000,
+                time_scale: 3.0,
+                ..AppSettings::default()
+            }
+        );
+
+        let path = write_temp("older.json", r#"{ "steps_per_frame": 12 }"#);
+        let settings = AppSettings::load(&path).unwrap();
+
+        assert_eq!(settings.time_scale, 12.0);
+        assert_eq!(settings.max_steps_per_frame, 12);
+    }
+
+    #[test]
+    fn loads_flat_species_fields() {
+        let path = write_temp(
+            "flat.toml",
+            r#"
+                num_agents = 1000
+                move_speed = 20.0
+                sensor_size = 2
+
+                [[species]]
+                [[species]]
+            "#,
+        );
+        let settings = AppSettings::load(&path).unwrap();
+
+        assert_eq!(settings.species.len(), 2);
+
+        for species in &settings.species {
+            assert_eq!(species.move_speed, 20.0);
+            assert_eq!(species.sensor_size, 2);
+        }
+
+        assert_eq!(settings.move_speed, None);
+        settings.validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_formats() {
+        let path = write_temp("unknown.toml", "widht = 320");
+        assert!(matches!(
+            AppSettings::load(&path),
+            Err(SettingsError::Parse { .. })
+        ));
+
+        let path = write_temp("settings.yaml", "width: 320");
+        assert!(matches!(
+            AppSettings::load(&path),
+            Err(SettingsError::UnsupportedFormat(_))
+        ));
+    }
+
+    fn invalid_field(settings: &AppSettings) -> &'static str {
+        match settings.validate() {
+            Err(SettingsError::Invalid { field, .. }) => field,
+            other => panic!("expected an invalid setting, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validates_defaults() {
+        AppSettings::default().validate().unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_sizes_and_counts() {
+        let defaults = AppSettings::default();
+
+        let cases = [
+            (
+                "width",
+                AppSettings {
+                    width: 0,
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "height",
+                AppSettings {
+                    height: 0,
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "num_agents",
+                AppSettings {
+                    num_agents: 0,
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "num_agents",
+                AppSettings {
+                    num_agents: MAX_AGENTS + 1,
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "species",
+                AppSettings {
+                    species: Vec::new(),
+                    ..defaults.clone()
+                },
+            ),
+            (
+                "species",
+                AppSettings {
+                    species: vec![SpeciesSettings::default(); MAX_SPECIES + 1],
+                    ..defaults.clone()
+                },
+            ),
+        ];
+
+        for (field, settings) in &cases {
+            assert_eq!(invalid_field(settings), *field);
+        }
+
+        AppSettings {
+            num_agents: MAX_AGENTS,
+            species: vec![SpeciesSettings::default(); MAX_SPECIES],
+            ..defaults
+        }
+        .validate()
+        .unwrap();
+    }
+
+    #[test]
+    fn rejects_bad_numbers() {
+        let mut settings = AppSettings::default();
+        settings.species[0].turn_speed = f32::NAN;
+        assert_eq!(invalid_field(&settings), "turn_speed");
+
+        let settings = AppSettings {
+            decay_rate: -1.0,
+            ..AppSettings::default()
+        };
+        assert_eq!(invalid_field(&settings), "decay_rate");
+
+        let settings = AppSettings {
+            spawn: SpawnMode::Ring {
+                radius: -5.0,
+                facing: Facing::Inward,
+            },
+            ..AppSettings::default()
+        };
+        assert_eq!(invalid_field(&settings), "spawn.radius");
+    }
+
+    #[test]
+    fn rejects_oversized_sensors() {
+        let mut settings = AppSettings::default();
+        settings.species[0].sensor_size = MAX_SENSOR_SIZE;
+        assert!(settings.validate().is_ok());
+
+        settings.species[0].sensor_size = MAX_SENSOR_SIZE + 1;
+        assert_eq!(invalid_field(&settings), "sensor_size");
+
+        settings.species[0].sensor_size = -1;
+        assert_eq!(invalid_field(&settings), "sensor_size");
+    }
+}
@@ -1,156 +1,267 @@
-use std::f32::consts::PI;
+use std::rc::Rc;
 
 use bytemuck::{Pod, Zeroable};
-use rand::{distributions::Uniform, prelude::Distribution};
+use half::f16;
 use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 
 use crate::{
-    app::AppSettings,
+    agent_buffers::AgentBuffers,
+    agents::Slots,
+    brush::{BrushBuffer, Stroke, Tool},
+    capture,
+    clock::StepClock,
+    food, obstacles,
     pipeline::{
-        BlitPipeline, BlitSettings, ClearPipeline, ClearSetup, CopyAgentMapPipeline,
-        DiffusePipeline, DiffuseSettings, Pipeline, RenderPipeline, RenderSettings,
-        SlimeSimPipeline, SlimeSimSetup, TimeBuffer,
+        BlitPipeline, BlitSettings, ClearPipeline, ClearSetup, CompactPipeline, CompactSetup,
+        CopyAgentMapPipeline, DepositPipeline, DepositSetup, DiffusePipeline, DiffuseSettings,
+        Pipeline, RenderPipeline, RenderSettings, ResamplePipeline, ResampleSetup,
+        SlimeSimPipeline, SlimeSimSetup, SpawnBuffer, SpawnPipeline, SpawnSetup, TimeBuffer,
+        Viewport,
     },
+    settings::AppSettings,
+    simulation::Simulation,
+    snapshot::Snapshot,
+    spawn,
 };
 
+/// Simulated time per step, in seconds.
+pub const STEP_DELTA_TIME: f32 = 0.005;
+
 pub struct ShaderPipeline {
     clear_pipeline: ClearPipeline,
     slime_sim_pipeline: SlimeSimPipeline,
+    deposit_pipeline: DepositPipeline,
     diffuse_pipeline: DiffusePipeline,
-    blit_diffuse_pipeline: BlitPipeline,
     blit_display_pipeline: BlitPipeline,
-    blit_trail_map_pipeline: BlitPipeline,
-    blit_trail_map_copy_pipeline: BlitPipeline,
     copy_agents_pipeline: CopyAgentMapPipeline,
     render_pipeline: RenderPipeline,
+    spawn_pipeline: SpawnPipeline,
+    state: SimState,
+    format: wgpu::TextureFormat,
+    viewport: Viewport,
     frame_num: usize,
+    clock: StepClock,
     settings: AppSettings,
+    stroke: Option<Stroke>,
+    /// Reads back how many agents the eraser has deleted, for the live count.
+    deleted_readback: capture::Readback,
+    /// The eraser ran since the deleted count was last read back.
+    recount: bool,
+}
+
+/// Simulation state that outlives a rebuild of the pipelines.
+struct SimState {
+    agents: Rc<AgentBuffers>,
+    slots: Slots,
+    /// Dead agents in `agents`, counted up by the simulation as the eraser
+    /// deletes them.
+    deleted: Rc<wgpu::Buffer>,
+    trail_map: wgpu::Texture,
+    /// The wall mask, kept on the CPU as the source of truth and mirrored in
+    /// `obstacle_map` for the shaders.
+    obstacles: Vec<u8>,
+    obstacle_map: wgpu::Texture,
+    /// The rasterized food sources, kept so rebuilds do not rasterize them
+    /// again, and mirrored in `food_map`. Empty when there is no food.
+    food: Vec<[f32; 4]>,
+    food_map: wgpu::Texture,
+    seed: u64,
+    /// Number of steps simulated so far, the clock that drives the steering
+    /// noise.
+    step: u32,
 }
 
 impl ShaderPipeline {
+    /// Starts a new run, spawning agents and optionally seeding the trail
+    /// map from the init image. Agents following the spawn pattern are
+    /// placed on the GPU.
     pub fn new(
         settings: AppSettings,
-        size: &PhysicalSize<u32>,
-        surface_configuration: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
     ) -> Self {
-        let mut rng = rand::thread_rng();
-        let agent_uniform = Uniform::new_inclusive(0.0, 1.0);
-        let agents = (0..settings.num_agents)
-            .into_iter()
-            .map(|_| {
-                let start_pos = [size.width as f32 / 2.0, size.height as f32 / 2.0];
-
-                let random_angle: f32 = agent_uniform.sample(&mut rng) * PI * 2.0;
-
-                Agent {
-                    position: start_pos,
-                    angle: random_angle,
-                    _padding: 0,
-                }
-            })
-            .collect::<Vec<Agent>>();
-
-        let agent_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("slime::shader::simulation::agents_buffer"),
-            contents: bytemuck::cast_slice(&agents),
-            usage: wgpu::BufferUsages::STORAGE,
-        });
-
-        let trail_map = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("slime::shader::simulation::texture"),
-            size: wgpu::Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let trail_map_copy = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("slime::shader::simulation::trail_map_copy"),
-            size: wgpu::Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let diffuse_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
+        let start = spawn::Start::new(settings);
+
+        if !start.follows_pattern() {
+            return Self::from_snapshot(&start.into_snapshot(), format, viewport, device, queue);
+        }
+
+        let pattern = start.pattern();
+        let slots = Slots::new(pattern.num_agents as usize);
+
+        let mut pipeline = Self::upload(&start.snapshot, slots, format, viewport, device, queue);
+        pipeline.place_agents(&pattern, device, queue);
+
+        pipeline
+    }
+
+    /// Uploads a saved run and carries on from its last step.
+    pub fn from_snapshot(
+        snapshot: &Snapshot,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let slots = Slots::for_agents(&snapshot.agents);
+
+        Self::upload(snapshot, slots, format, viewport, device, queue)
+    }
+
+    /// Uploads `snapshot` into agent storage laid out by `slots`.
+    fn upload(
+        snapshot: &Snapshot,
+        slots: Slots,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let settings = AppSettings {
+            num_agents: slots.live(),
+            ..snapshot.settings.clone()
+        };
+        let size = PhysicalSize::new(settings.width, settings.height);
+
+        let agents = AgentBuffers::new(device, queue, slots.capacity, &snapshot.agents);
+        let deleted = create_deleted_buffer(device, slots.count - slots.live());
+
+        let trail_map = create_sim_texture(
+            device,
+            "slime::shader::simulation::texture",
+            &size,
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        );
+
+        queue.write_texture(
+            trail_map.as_image_copy(),
+            bytemuck::cast_slice(&snapshot.trail_map),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(
+                    size.width * std::mem::size_of::<[f16; 4]>() as u32,
+                ),
+                rows_per_image: std::num::NonZeroU32::new(size.height),
             },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let display_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: wgpu::Extent3d {
-                width: size.width,
-                height: size.height,
-                depth_or_array_layers: 1,
+            trail_map.size(),
+        );
+
+        let obstacle_map = obstacles::create_texture(device, &size);
+        obstacles::upload(queue, &obstacle_map, &snapshot.obstacles);
+
+        let food = rasterize_food(&settings, &size);
+        let food_map = create_food_map(device, queue, &food, &size);
+
+        Self::with_state(
+            settings,
+            &size,
+            format,
+            viewport,
+            SimState {
+                agents: Rc::new(agents),
+                slots,
+                deleted,
+                trail_map,
+                obstacles: snapshot.obstacles.clone(),
+                obstacle_map,
+                food,
+                food_map,
+                seed: snapshot.seed,
+                step: snapshot.step,
             },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
+            device,
+        )
+    }
+
+    /// Builds the pipelines around an existing agent buffer and trail map.
+    fn with_state(
+        settings: AppSettings,
+        size: &PhysicalSize<u32>,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        state: SimState,
+        device: &wgpu::Device,
+    ) -> Self {
+        let trail_map = &state.trail_map;
+
+        // Agents count themselves into their texel, the deposit pass adds the
+        // counts to the trail map in the deposit map, and diffuse reads that
+        // back into the trail map. Counting keeps the result independent of
+        // the order agents run in.
+        let deposit_map = create_sim_texture(
+            device,
+            "slime::shader::simulation::deposit_map",
+            size,
+            wgpu::TextureUsages::empty(),
+        );
+
+        let deposit_counts = Rc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("slime::shader::simulation::deposit_counts"),
+            size: (size.width * size.height) as wgpu::BufferAddress
+                * 4
+                * std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        }));
+
+        let obstacle_view = || {
+            state
+                .obstacle_map
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+
+        let display_texture = create_sim_texture(
+            device,
+            "slime::shader::simulation::display_texture",
+            size,
+            wgpu::TextureUsages::empty(),
+        );
 
         let render_setup = RenderSettings {
-            format: surface_configuration.format,
+            format,
+            texture_view: display_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            obstacle_view: obstacle_view(),
             width: size.width,
             height: size.height,
-            texture_view: display_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            viewport,
         };
 
         let diffuse_settings = DiffuseSettings {
             width: size.width,
             height: size.height,
+            deposit_map: deposit_map.create_view(&wgpu::TextureViewDescriptor::default()),
             trail_map_texture: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
-            diffuse_texture: diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            obstacle_map: obstacle_view(),
+        };
+
+        // Food is added along with the deposits, and the paint brush once
+        // they are diffused.
+        let deposit_setup = DepositSetup {
+            width: size.width,
+            height: size.height,
+            trail_map_texture: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
+            deposit_counts: deposit_counts.clone(),
+            deposit_map: deposit_map.create_view(&wgpu::TextureViewDescriptor::default()),
+            food_map: state
+                .food_map
+                .create_view(&wgpu::TextureViewDescriptor::default()),
         };
 
         let slime_sim_setup = SlimeSimSetup {
             width: size.width,
             height: size.height,
-            format: surface_configuration.format,
-            binding: agent_buffer,
+            agents: state.agents.clone(),
             trail_map_texture_view: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
-            trail_map_write_texture_view: trail_map_copy
-                .create_view(&wgpu::TextureViewDescriptor::default()),
+            deposit_counts,
+            deleted: state.deleted.clone(),
+            obstacle_view: obstacle_view(),
             display_texture_view: display_texture
                 .create_view(&wgpu::TextureViewDescriptor::default()),
-            num_agents: agents.len() as u32,
+            num_agents: state.slots.count as u32,
+            seed: shader_seed(state.seed),
         };
 
         let clear_setup = ClearSetup {
@@ -160,19 +271,18 @@ impl ShaderPipeline {
         };
 
         let slime_sim_pipeline = SlimeSimPipeline::new(device, &settings, &slime_sim_setup);
+        let deposit_pipeline = DepositPipeline::new(device, &settings, &deposit_setup);
         let diffuse_pipeline = DiffusePipeline::new(device, &settings, &diffuse_settings);
         let clear_pipeline = ClearPipeline::new(device, &settings, &clear_setup);
         let copy_agents_pipeline = CopyAgentMapPipeline::new(device, &settings, &slime_sim_setup);
         let render_pipeline = RenderPipeline::new(device, &settings, &render_setup);
-
-        let blit_diffuse_settings = BlitSettings {
-            width: size.width,
-            height: size.height,
-            input_texture: diffuse_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-            output_texture: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
-        };
-
-        let blit_diffuse_pipeline = BlitPipeline::new(device, &settings, &blit_diffuse_settings);
+        let spawn_pipeline = SpawnPipeline::new(
+            device,
+            &settings,
+            &SpawnSetup {
+                agents: state.agents.clone(),
+            },
+        );
 
         let blit_display_settings = BlitSettings {
             width: size.width,
@@ -183,83 +293,580 @@ impl ShaderPipeline {
 
         let blit_display_pipeline = BlitPipeline::new(device, &settings, &blit_display_settings);
 
-        let blip_trail_map_settings = BlitSettings {
-            width: size.width,
-            height: size.height,
-            input_texture: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
-            output_texture: trail_map_copy.create_view(&wgpu::TextureViewDescriptor::default()),
-        };
-
-        let blit_trail_map_pipeline =
-            BlitPipeline::new(device, &settings, &blip_trail_map_settings);
-
-        let blip_trail_map_copy_settings = BlitSettings {
-            width: size.width,
-            height: size.height,
-            input_texture: trail_map_copy.create_view(&wgpu::TextureViewDescriptor::default()),
-            output_texture: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
-        };
-
-        let blit_trail_map_copy_pipeline =
-            BlitPipeline::new(device, &settings, &blip_trail_map_copy_settings);
+        // A rebuild drops any count on its way back, so it is read again.
+        let recount = state.slots.is_culled();
 
         Self {
             clear_pipeline,
             slime_sim_pipeline,
+            deposit_pipeline,
             diffuse_pipeline,
             copy_agents_pipeline,
             render_pipeline,
-            blit_diffuse_pipeline,
             blit_display_pipeline,
-            blit_trail_map_pipeline,
-            blit_trail_map_copy_pipeline,
+            spawn_pipeline,
+            state,
+            format,
+            viewport,
             settings,
             frame_num: 0,
+            clock: StepClock::default(),
+            stroke: None,
+            deleted_readback: capture::Readback::new(
+                device,
+                std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            ),
+            recount,
         }
     }
 
-    pub fn swap_buffers(&mut self) {
-        self.settings.agents_only = !self.settings.agents_only;
+    /// Swaps in pipelines built around `state`, carrying on the clock.
+    fn rebuild(&mut self, settings: AppSettings, state: SimState, device: &wgpu::Device) {
+        let size = PhysicalSize::new(settings.width, settings.height);
+        let mut rebuilt =
+            Self::with_state(settings, &size, self.format, self.viewport, state, device);
+
+        rebuilt.frame_num = self.frame_num;
+        rebuilt.clock = std::mem::take(&mut self.clock);
+        rebuilt.stroke = self.stroke;
+
+        *self = rebuilt;
     }
 
-    pub fn render(
+    /// Places the agents `spawn` asks for by the spawn pattern.
+    fn place_agents(&mut self, spawn: &SpawnBuffer, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.spawn_pipeline.update(queue, spawn);
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let frame = self
+            .state
+            .trail_map
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.spawn_pipeline.execute(&mut command_encoder, &frame);
+        queue.submit(Some(command_encoder.finish()));
+    }
+
+    /// Advances the simulation by one tick of the fixed clock. Each step is
+    /// submitted on its own so it sees its own time uniform.
+    fn simulate_step(
         &mut self,
         frame: &wgpu::TextureView,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
-        time_buffer: &TimeBuffer,
     ) {
+        let time_buffer = TimeBuffer {
+            time: self.state.step,
+            delta_time: STEP_DELTA_TIME,
+        };
+
+        let paint = BrushBuffer::paint(self.stroke, &self.settings);
+        let eraser = BrushBuffer::eraser(self.stroke, &self.settings);
+
+        if eraser.is_active() {
+            self.state.slots.cull();
+            self.recount = true;
+        }
+
+        if let Some(stroke) = self.stroke.filter(|stroke| stroke.tool == Tool::Spawn) {
+            self.spawn_agents(stroke, frame, device, queue);
+        }
+
+        self.slime_sim_pipeline
+            .set_attractor(queue, &BrushBuffer::attractor(self.stroke, &self.settings));
+        self.slime_sim_pipeline.set_eraser(queue, &eraser);
+        self.slime_sim_pipeline.update(queue, &time_buffer);
+        self.deposit_pipeline.update(queue, &time_buffer);
+        self.diffuse_pipeline.update(queue, &time_buffer);
+        self.diffuse_pipeline.set_brush(queue, &paint);
+
         let mut command_encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
-        self.slime_sim_pipeline.update(&queue, &time_buffer);
-        self.diffuse_pipeline.update(&queue, &time_buffer);
+        self.slime_sim_pipeline.execute(&mut command_encoder, frame);
+        self.deposit_pipeline.execute(&mut command_encoder, frame);
+        self.diffuse_pipeline.execute(&mut command_encoder, frame);
 
-        for _ in 0..self.settings.steps_per_frame {
-            self.blit_trail_map_pipeline
-                .execute(&mut command_encoder, &frame);
-            self.slime_sim_pipeline
-                .execute(&mut command_encoder, &frame);
-            self.blit_trail_map_copy_pipeline
-                .execute(&mut command_encoder, &frame);
+        queue.submit(Some(command_encoder.finish()));
 
-            self.diffuse_pipeline.execute(&mut command_encoder, &frame);
+        self.state.step = self.state.step.wrapping_add(1);
+    }
 
-            self.blit_diffuse_pipeline
-                .execute(&mut command_encoder, &frame);
+    /// Adds a burst from the spawn brush after the agents in use, compacting
+    /// away deleted agents first if that makes room, and growing the buffers
+    /// if it does not.
+    fn spawn_agents(
+        &mut self,
+        stroke: Stroke,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let burst = self.settings.brush.spawn_count;
+
+        if self.state.slots.needs_compaction(burst) {
+            // Compacting starts the count of deleted agents over, so it
+            // waits until every agent deleted so far is counted.
+            if self.is_counting() {
+                return;
+            }
+
+            self.compact(frame, device, queue);
         }
 
+        if let Some(capacity) = self.state.slots.growth(burst) {
+            self.grow(capacity, device, queue);
+        }
+
+        let first_index = self.state.slots.count;
+        let count = self.state.slots.reserve(burst);
+
+        let agents = spawn::brush_agents(
+            &self.settings,
+            stroke.position,
+            first_index,
+            count,
+            &mut spawn::brush_rng(self.state.seed, self.state.step),
+        );
+
+        self.state.agents.write(queue, first_index, &agents);
+
+        let num_agents = self.state.slots.count;
+        self.settings.num_agents = self.state.slots.live();
+        self.slime_sim_pipeline
+            .set_agent_count(queue, num_agents as u32);
+        self.copy_agents_pipeline
+            .set_agent_count(queue, num_agents as u32);
+    }
+
+    /// Packs the live agents into the front of the buffers, in order, and
+    /// starts the count of deleted agents over. The agents never leave the
+    /// GPU; they are compacted into spare buffers and copied back.
+    fn compact(&mut self, frame: &wgpu::TextureView, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let live = self.state.slots.live();
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        if live > 0 {
+            let compacted = Rc::new(AgentBuffers::new(device, queue, live, &[]));
+
+            self.compact_into(&compacted, &mut command_encoder, frame, device);
+            self.state
+                .agents
+                .copy_from(&mut command_encoder, &compacted, live);
+        }
+
+        queue.write_buffer(&self.state.deleted, 0, bytemuck::bytes_of(&0u32));
+        queue.submit(Some(command_encoder.finish()));
+
+        self.state.slots.compacted(live);
+    }
+
+    /// Records a pass that packs the live agents, in order, into the front
+    /// of `compacted`.
+    fn compact_into(
+        &self,
+        compacted: &Rc<AgentBuffers>,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+    ) {
+        let compact_setup = CompactSetup {
+            agents: self.state.agents.clone(),
+            num_agents: self.state.slots.count as u32,
+            compacted: compacted.clone(),
+        };
+
+        CompactPipeline::new(device, &self.settings, &compact_setup).execute(encoder, frame);
+    }
+
+    /// Moves the agents into buffers of `capacity` slots.
+    fn grow(&mut self, capacity: usize, device: &wgpu::Device, queue: &wgpu::Queue) {
+        log::info!(
+            "Growing agent storage from {} to {} slots",
+            self.state.slots.capacity,
+            capacity
+        );
+
+        let agent_buffers = Rc::new(AgentBuffers::new(device, queue, capacity, &[]));
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        agent_buffers.copy_from(
+            &mut command_encoder,
+            &self.state.agents,
+            self.state.slots.count,
+        );
+
+        let mut slots = self.state.slots;
+        slots.grown(capacity);
+
+        self.replace_agents(
+            agent_buffers,
+            slots,
+            self.state.deleted.clone(),
+            command_encoder,
+            device,
+            queue,
+        );
+    }
+
+    /// Rebuilds every pipeline that binds the agents around `agents`. The
+    /// trail map is copied over by `command_encoder`, which may already hold
+    /// the agents' copy, and the walls and food are carried over as they are.
+    fn replace_agents(
+        &mut self,
+        agents: Rc<AgentBuffers>,
+        slots: Slots,
+        deleted: Rc<wgpu::Buffer>,
+        mut command_encoder: wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let size = PhysicalSize::new(self.settings.width, self.settings.height);
+
+        let trail_map = create_sim_texture(
+            device,
+            "slime::shader::simulation::texture",
+            &size,
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        );
+
+        command_encoder.copy_texture_to_texture(
+            self.state.trail_map.as_image_copy(),
+            trail_map.as_image_copy(),
+            trail_map.size(),
+        );
+        queue.submit(Some(command_encoder.finish()));
+
+        let obstacle_map = obstacles::create_texture(device, &size);
+        obstacles::upload(queue, &obstacle_map, &self.state.obstacles);
+
+        let food_map = create_food_map(device, queue, &self.state.food, &size);
+
+        self.rebuild(
+            self.settings.clone(),
+            SimState {
+                agents,
+                slots,
+                deleted,
+                trail_map,
+                obstacles: self.state.obstacles.clone(),
+                obstacle_map,
+                food: self.state.food.clone(),
+                food_map,
+                seed: self.state.seed,
+                step: self.state.step,
+            },
+            device,
+        );
+    }
+
+    /// Waits for the count of deleted agents, for the rebuilds that cannot
+    /// carry on without it.
+    fn wait_for_count(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        while self.is_counting() {
+            self.count_deleted(device, queue);
+            device.poll(wgpu::Maintain::Wait);
+        }
+    }
+
+    /// Brings the live agent count up to date with the eraser. The count is
+    /// read back without waiting, so it trails the GPU by a frame or so.
+    fn count_deleted(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if let Some(bytes) = self.deleted_readback.poll(device) {
+            let deleted: u32 = bytemuck::pod_read_unaligned(&bytes);
+            self.state.slots.counted(deleted as usize);
+            self.settings.num_agents = self.state.slots.live();
+        }
+
+        if self.recount
+            && self
+                .deleted_readback
+                .start(device, queue, &self.state.deleted)
+        {
+            self.recount = false;
+        }
+    }
+
+    /// Whether agents may have been deleted that the books do not hold yet.
+    fn is_counting(&self) -> bool {
+        self.recount || self.deleted_readback.is_pending()
+    }
+}
+
+impl Simulation for ShaderPipeline {
+    fn settings(&self) -> &AppSettings {
+        &self.settings
+    }
+
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &AppSettings) {
+        let settings = self.settings.updated_with(settings);
+
+        if settings.food != self.settings.food {
+            let size = PhysicalSize::new(settings.width, settings.height);
+            self.state.food = rasterize_food(&settings, &size);
+
+            write_food_map(queue, &self.state.food_map, &self.state.food);
+        }
+
+        self.slime_sim_pipeline.set_settings(queue, &settings);
+        self.deposit_pipeline.set_settings(queue, &settings);
+        self.diffuse_pipeline.set_settings(queue, &settings);
+        self.render_pipeline.set_settings(queue, &settings);
+
+        self.settings = settings;
+    }
+
+    fn resize(&mut self, queue: &wgpu::Queue, viewport: Viewport) {
+        self.viewport = viewport;
+        self.render_pipeline.update(queue, &viewport);
+    }
+
+    fn resize_simulation(
+        &mut self,
+        size: &PhysicalSize<u32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) {
+        let old_size = PhysicalSize::new(self.settings.width, self.settings.height);
+
+        if size.width == 0 || size.height == 0 || *size == old_size {
+            return;
+        }
+
+        let trail_map = create_sim_texture(
+            device,
+            "slime::shader::simulation::texture",
+            size,
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        );
+
+        let resample_setup = ResampleSetup {
+            old_width: old_size.width,
+            old_height: old_size.height,
+            new_width: size.width,
+            new_height: size.height,
+            old_trail_map: self
+                .state
+                .trail_map
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+            new_trail_map: trail_map.create_view(&wgpu::TextureViewDescriptor::default()),
+            agents: self.state.agents.clone(),
+            num_agents: self.state.slots.count as u32,
+        };
+
+        let resample_pipeline = ResamplePipeline::new(device, &self.settings, &resample_setup);
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        resample_pipeline.execute(&mut command_encoder, &resample_setup.new_trail_map);
+        queue.submit(Some(command_encoder.finish()));
+
+        log::info!(
+            "Resized simulation from {}x{} to {}x{}",
+            old_size.width,
+            old_size.height,
+            size.width,
+            size.height
+        );
+
+        let settings = AppSettings {
+            width: size.width,
+            height: size.height,
+            ..self.settings.clone()
+        };
+
+        let obstacles = obstacles::resample(&self.state.obstacles, &old_size, size);
+        let obstacle_map = obstacles::create_texture(device, size);
+        obstacles::upload(queue, &obstacle_map, &obstacles);
+
+        let food = if self.state.food.is_empty() {
+            Vec::new()
+        } else {
+            obstacles::resample(&self.state.food, &old_size, size)
+        };
+        let food_map = create_food_map(device, queue, &food, size);
+
+        self.rebuild(
+            settings,
+            SimState {
+                agents: self.state.agents.clone(),
+                slots: self.state.slots,
+                deleted: self.state.deleted.clone(),
+                trail_map,
+                obstacles,
+                obstacle_map,
+                food,
+                food_map,
+                seed: self.state.seed,
+                step: self.state.step,
+            },
+            device,
+        );
+    }
+
+    fn set_agent_count(&mut self, count: usize, device: &wgpu::Device, queue: &wgpu::Queue) {
+        log::info!(
+            "Changing agent count from {} to {}",
+            self.settings.num_agents,
+            count
+        );
+
+        let slots = Slots::new(count);
+        let agent_buffers = Rc::new(AgentBuffers::new(device, queue, slots.capacity, &[]));
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        // Agents are kept from the front, compacting deleted agents away
+        // first if there are any. They never leave the GPU.
+        self.wait_for_count(device, queue);
+        let kept = count.min(self.state.slots.live());
+
+        if self.state.slots.live() < self.state.slots.count {
+            let frame = self
+                .state
+                .trail_map
+                .create_view(&wgpu::TextureViewDescriptor::default());
+
+            self.compact_into(&agent_buffers, &mut command_encoder, &frame, device);
+        } else {
+            agent_buffers.copy_from(&mut command_encoder, &self.state.agents, kept);
+        }
+
+        self.settings.num_agents = count;
+        self.replace_agents(
+            agent_buffers,
+            slots,
+            create_deleted_buffer(device, 0),
+            command_encoder,
+            device,
+            queue,
+        );
+
+        if kept < count {
+            let spawn = SpawnBuffer::new(
+                &self.settings,
+                self.state.seed,
+                self.state.step,
+                kept,
+                count - kept,
+            );
+
+            self.place_agents(&spawn, device, queue);
+        }
+    }
+
+    fn set_brush(&mut self, stroke: Option<Stroke>) {
+        self.stroke = stroke;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.clock.is_paused()
+    }
+
+    fn step_count(&self) -> u32 {
+        self.state.step
+    }
+
+    fn toggle_pause(&mut self) {
+        self.clock.toggle_pause();
+    }
+
+    fn step(&mut self, count: usize) {
+        self.clock.step(count);
+    }
+
+    fn swap_buffers(&mut self) {
+        self.settings.agents_only = !self.settings.agents_only;
+    }
+
+    fn screenshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<image::RgbaImage> {
+        capture::screenshot(
+            device,
+            queue,
+            &self.render_pipeline,
+            self.format,
+            self.viewport,
+        )
+    }
+
+    fn trail_map_image(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> image::Rgba32FImage {
+        let texels = capture::read_texture(
+            device,
+            queue,
+            &self.state.trail_map,
+            std::mem::size_of::<[f16; 4]>() as u32,
+        );
+
+        let values = bytemuck::cast_slice::<u8, f16>(&texels)
+            .iter()
+            .map(|value| value.to_f32())
+            .collect();
+
+        let image =
+            image::Rgba32FImage::from_raw(self.settings.width, self.settings.height, values)
+                .expect("Trail map readback has the wrong size");
+
+        // Row zero of the simulation is drawn at the bottom of the window.
+        image::imageops::flip_vertical(&image)
+    }
+
+    fn snapshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Snapshot {
+        let agents = self
+            .state
+            .agents
+            .read(device, queue, self.state.slots.count);
+        let trail_map = capture::read_texture(
+            device,
+            queue,
+            &self.state.trail_map,
+            std::mem::size_of::<[f16; 4]>() as u32,
+        );
+
+        Snapshot {
+            // A snapshot counts the slots it stores, dead agents included.
+            settings: AppSettings {
+                num_agents: agents.len(),
+                ..self.settings.clone()
+            },
+            seed: self.state.seed,
+            step: self.state.step,
+            agents,
+            trail_map: read_unaligned(&trail_map),
+            obstacles: self.state.obstacles.clone(),
+        }
+    }
+
+    fn render(
+        &mut self,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    ) {
+        for _ in 0..self.clock.advance(&self.settings, elapsed) {
+            self.simulate_step(frame, device, queue);
+        }
+
+        self.count_deleted(device, queue);
+
+        let mut command_encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
         if self.settings.agents_only {
-            self.clear_pipeline.execute(&mut command_encoder, &frame);
+            self.clear_pipeline.execute(&mut command_encoder, frame);
 
             self.copy_agents_pipeline
-                .execute(&mut command_encoder, &frame);
+                .execute(&mut command_encoder, frame);
         } else {
             self.blit_display_pipeline
-                .execute(&mut command_encoder, &frame);
+                .execute(&mut command_encoder, frame);
         }
 
-        self.render_pipeline.execute(&mut command_encoder, &frame);
+        self.render_pipeline.execute(&mut command_encoder, frame);
 
         self.frame_num += 1;
 
@@ -267,10 +874,359 @@ impl ShaderPipeline {
     }
 }
 
+/// The counter of agents the eraser has deleted, starting from `deleted`.
+fn create_deleted_buffer(device: &wgpu::Device, deleted: usize) -> Rc<wgpu::Buffer> {
+    Rc::new(
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("slime::shader::simulation::deleted"),
+            contents: bytemuck::bytes_of(&(deleted as u32)),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+        }),
+    )
+}
+
+/// Folds the run seed into the 32 bits the shaders hash with.
+pub fn shader_seed(seed: u64) -> u32 {
+    (seed ^ (seed >> 32)) as u32
+}
+
+/// Reinterprets read back bytes, which carry no alignment guarantee.
+pub fn read_unaligned<T: Pod>(bytes: &[u8]) -> Vec<T> {
+    bytes
+        .chunks_exact(std::mem::size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
+
+/// Rasterizes the food sources of `settings`, or nothing without any.
+fn rasterize_food(settings: &AppSettings, size: &PhysicalSize<u32>) -> Vec<[f32; 4]> {
+    if settings.food.is_empty() {
+        return Vec::new();
+    }
+
+    food::food_map(settings, size)
+}
+
+/// An `Rgba32Float` texture holding `food`, a map from `rasterize_food`.
+fn create_food_map(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    food: &[[f32; 4]],
+    size: &PhysicalSize<u32>,
+) -> wgpu::Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("slime::shader::simulation::food_map"),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    if !food.is_empty() {
+        write_food_map(queue, &texture, food);
+    }
+
+    texture
+}
+
+/// Writes `food_map` to `texture`, clearing it when the map is empty.
+fn write_food_map(queue: &wgpu::Queue, texture: &wgpu::Texture, food_map: &[[f32; 4]]) {
+    let size = texture.size();
+
+    let cleared;
+    let food_map = if food_map.is_empty() {
+        cleared = vec![[0.0; 4]; (size.width * size.height) as usize];
+        &cleared
+    } else {
+        food_map
+    };
+
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(food_map),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(
+                size.width * std::mem::size_of::<[f32; 4]>() as u32,
+            ),
+            rows_per_image: std::num::NonZeroU32::new(size.height),
+        },
+        size,
+    );
+}
+
+fn create_sim_texture(
+    device: &wgpu::Device,
+    label: &str,
+    size: &PhysicalSize<u32>,
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba16Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | usage,
+        view_formats: &[],
+    })
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct Agent {
-    position: [f32; 2],
-    angle: f32,
-    _padding: i32,
+    pub position: [f32; 2],
+    pub angle: f32,
+    pub species_index: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agents,
+        cpu::{
+            tests::{compute_device, delete_stroke, frame, live_agents, parity_settings},
+            CpuSimulation,
+        },
+    };
+
+    /// `snapshot` uploaded to the GPU, with a frame the size of its trail map
+    /// to render to.
+    fn pipeline(
+        snapshot: &Snapshot,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> (ShaderPipeline, wgpu::TextureView) {
+        let viewport = Viewport {
+            width: snapshot.settings.width,
+            height: snapshot.settings.height,
+        };
+        let frame = frame(device, [viewport.width, viewport.height]);
+        let pipeline = ShaderPipeline::from_snapshot(
+            snapshot,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            viewport,
+            device,
+            queue,
+        );
+
+        (pipeline, frame)
+    }
+
+    /// A fresh run of `settings` with every third agent deleted.
+    fn thinned_snapshot(settings: AppSettings) -> Snapshot {
+        let mut snapshot = spawn::initial_snapshot(settings);
+        for agent in snapshot.agents.iter_mut().step_by(3) {
+            agent.species_index = agents::DEAD;
+        }
+
+        snapshot
+    }
+
+    #[test]
+    fn counts_deleted_agents() {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        let (mut gpu, frame) =
+            pipeline(&spawn::initial_snapshot(parity_settings()), &device, &queue);
+        gpu.set_brush(delete_stroke());
+        gpu.step(3);
+        gpu.render(&frame, &device, &queue, 0.0);
+
+        // The count arrives without the render waiting for it.
+        for _ in 0..100 {
+            if gpu.settings().num_agents < 5_000 {
+                break;
+            }
+
+            device.poll(wgpu::Maintain::Wait);
+            gpu.render(&frame, &device, &queue, 0.0);
+        }
+
+        let snapshot = gpu.snapshot(&device, &queue);
+        assert_eq!(snapshot.agents.len(), 5_000);
+        assert_eq!(gpu.settings().num_agents, live_agents(&snapshot.agents));
+        assert!(gpu.settings().num_agents < 5_000);
+    }
+
+    #[test]
+    fn compacts_like_the_cpu() {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        let snapshot = thinned_snapshot(parity_settings());
+        let live = live_agents(&snapshot.agents);
+
+        // Fewer agents than are alive, and more.
+        for count in [1_000, 4_000] {
+            let (mut gpu, _) = pipeline(&snapshot, &device, &queue);
+            assert_eq!(gpu.settings().num_agents, live);
+            gpu.set_agent_count(count, &device, &queue);
+
+            let mut cpu = CpuSimulation::from_snapshot(&snapshot);
+            cpu.set_agent_count(count);
+
+            let gpu = gpu.snapshot(&device, &queue);
+            let cpu = cpu.snapshot();
+            let kept = count.min(live);
+
+            assert_eq!(gpu.agents.len(), count);
+            assert_eq!(
+                bytemuck::cast_slice::<Agent, u8>(&gpu.agents[..kept]),
+                bytemuck::cast_slice::<Agent, u8>(&cpu.agents[..kept])
+            );
+        }
+    }
+
+    #[test]
+    fn compacts_for_a_spawn_without_waiting() {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        let mut settings = parity_settings();
+        settings.resize_species(3);
+        // More than the spare slots, so the spawn needs the deleted ones.
+        settings.brush.spawn_count = 4_500;
+
+        let snapshot = thinned_snapshot(settings);
+        let survivors = snapshot
+            .agents
+            .iter()
+            .filter(|agent| agents::is_alive(agent))
+            .map(|agent| agent.species_index)
+            .collect::<Vec<_>>();
+
+        let (mut gpu, frame) = pipeline(&snapshot, &device, &queue);
+        gpu.set_brush(Some(Stroke {
+            position: [48.0, 32.0],
+            tool: Tool::Spawn,
+        }));
+
+        // The spawn is held back until the deleted agents are counted.
+        for _ in 0..100 {
+            if gpu.settings().num_agents > survivors.len() {
+                break;
+            }
+
+            gpu.step(1);
+            gpu.render(&frame, &device, &queue, 0.0);
+            device.poll(wgpu::Maintain::Wait);
+        }
+
+        let agents = gpu.snapshot(&device, &queue).agents;
+        assert_eq!(agents.len(), survivors.len() + 4_500);
+        assert_eq!(gpu.settings().num_agents, agents.len());
+        assert!(agents.iter().all(agents::is_alive));
+
+        let species = agents[..survivors.len()]
+            .iter()
+            .map(|agent| agent.species_index)
+            .collect::<Vec<_>>();
+        assert_eq!(species, survivors);
+    }
+    #[test]
+    fn grows_for_a_spawn() {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        let mut settings = parity_settings();
+        settings.resize_species(3);
+        settings.brush.spawn_count = 20_000;
+
+        let snapshot = spawn::initial_snapshot(settings);
+        let species = |agents: &[Agent]| {
+            agents
+                .iter()
+                .map(|agent| agent.species_index)
+                .collect::<Vec<_>>()
+        };
+
+        let (mut gpu, frame) = pipeline(&snapshot, &device, &queue);
+        gpu.set_brush(Some(Stroke {
+            position: [48.0, 32.0],
+            tool: Tool::Spawn,
+        }));
+        gpu.step(2);
+        gpu.render(&frame, &device, &queue, 0.0);
+
+        let agents = gpu.snapshot(&device, &queue).agents;
+        assert_eq!(agents.len(), 45_000);
+        assert_eq!(gpu.settings().num_agents, 45_000);
+        assert_eq!(species(&agents[..5_000]), species(&snapshot.agents));
+    }
+    #[test]
+    fn same_seed_repeats_bit_for_bit() {
+        let Some((device, queue)) = compute_device() else {
+            return;
+        };
+
+        let run = || {
+            let settings = AppSettings {
+                seed: Some(42),
+                ..parity_settings()
+            };
+            let viewport = Viewport {
+                width: settings.width,
+                height: settings.height,
+            };
+            let frame = frame(&device, [viewport.width, viewport.height]);
+            let mut gpu = ShaderPipeline::new(
+                settings,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                viewport,
+                &device,
+                &queue,
+            );
+
+            // Five steps a frame, so thirty in all.
+            for _ in 0..6 {
+                gpu.step(5);
+                gpu.render(&frame, &device, &queue, 0.0);
+            }
+
+            gpu.snapshot(&device, &queue)
+        };
+
+        let trail_bits = |snapshot: &Snapshot| {
+            snapshot
+                .trail_map
+                .iter()
+                .flatten()
+                .map(|texel| texel.to_bits())
+                .collect::<Vec<_>>()
+        };
+
+        let (first, second) = (run(), run());
+        assert_eq!(first.step, 30);
+        assert_eq!(second.step, 30);
+        assert!(trail_bits(&first).iter().any(|&bits| bits != 0));
+        assert_eq!(trail_bits(&first), trail_bits(&second));
+        assert_eq!(
+            bytemuck::cast_slice::<Agent, u8>(&first.agents),
+            bytemuck::cast_slice::<Agent, u8>(&second.agents)
+        );
+    }
 }
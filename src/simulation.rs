@@ -0,0 +1,135 @@
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    app, brush::Stroke, cpu_pipeline::CpuPipeline, pipeline::Viewport, settings::AppSettings,
+    shader_pipeline::ShaderPipeline, snapshot::Snapshot,
+};
+
+/// A simulation backend, as driven by the window and the headless runner.
+/// Both backends present through wgpu; they differ in where the steps run.
+pub trait Simulation {
+    fn settings(&self) -> &AppSettings;
+
+    /// Applies changed settings. The texture size, agent count and number of
+    /// species are kept: the size and count have methods of their own,
+    /// `resize_simulation` and `set_agent_count`, and the species only change
+    /// with a restart.
+    fn set_settings(&mut self, queue: &wgpu::Queue, settings: &AppSettings);
+
+    /// Fits the presentation to a resized surface.
+    fn resize(&mut self, queue: &wgpu::Queue, viewport: Viewport);
+
+    /// Rebuilds the simulation at `size`, resampling the trail map and
+    /// scaling agent positions so the run carries on where it was.
+    fn resize_simulation(
+        &mut self,
+        size: &PhysicalSize<u32>,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    );
+
+    /// Grows or shrinks the population to `count` live agents, keeping the
+    /// agents already running. New agents are placed by the spawn pattern.
+    fn set_agent_count(&mut self, count: usize, device: &wgpu::Device, queue: &wgpu::Queue);
+
+    /// Holds the brush down at `stroke` for the steps to come, or lifts it.
+    fn set_brush(&mut self, stroke: Option<Stroke>);
+
+    fn is_paused(&self) -> bool;
+
+    /// Number of steps simulated since the start of the run.
+    fn step_count(&self) -> u32;
+
+    fn toggle_pause(&mut self);
+
+    /// Pauses the simulation and queues `count` steps, run over the next
+    /// renders at up to `max_steps_per_frame` each.
+    fn step(&mut self, count: usize);
+
+    fn swap_buffers(&mut self);
+
+    /// Draws the current display again into an offscreen texture the size of
+    /// the surface, as an 8-bit image without the parameter panel.
+    fn screenshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<image::RgbaImage>;
+
+    /// The raw trail map, one channel per species, with the full range of
+    /// the simulation rather than the tonemapped display.
+    fn trail_map_image(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> image::Rgba32FImage;
+
+    /// The full simulation state, to save and resume later.
+    fn snapshot(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Snapshot;
+
+    /// Advances the simulation by `elapsed` seconds of real time and draws
+    /// the result to `frame`.
+    fn render(
+        &mut self,
+        frame: &wgpu::TextureView,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        elapsed: f32,
+    );
+}
+
+/// Where the simulation steps run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Compute shaders, the default.
+    Gpu,
+    /// Rayon threads, for adapters that cannot run compute shaders.
+    Cpu,
+}
+
+impl Backend {
+    /// The GPU unless `force_cpu` is set or `adapter` has no compute
+    /// support.
+    pub fn for_adapter(adapter: &wgpu::Adapter, force_cpu: bool) -> Self {
+        if force_cpu {
+            Backend::Cpu
+        } else if app::supports_compute(adapter) {
+            Backend::Gpu
+        } else {
+            log::warn!(
+                "{} cannot run compute shaders, simulating on the CPU",
+                adapter.get_info().name
+            );
+            Backend::Cpu
+        }
+    }
+
+    /// Starts a new run, spawning agents and optionally seeding the trail
+    /// map from the init image.
+    pub fn start(
+        self,
+        settings: AppSettings,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Box<dyn Simulation> {
+        match self {
+            Backend::Gpu => Box::new(ShaderPipeline::new(
+                settings, format, viewport, device, queue,
+            )),
+            Backend::Cpu => Box::new(CpuPipeline::new(settings, format, viewport, device, queue)),
+        }
+    }
+
+    /// Carries on from a saved run.
+    pub fn resume(
+        self,
+        snapshot: &Snapshot,
+        format: wgpu::TextureFormat,
+        viewport: Viewport,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Box<dyn Simulation> {
+        match self {
+            Backend::Gpu => Box::new(ShaderPipeline::from_snapshot(
+                snapshot, format, viewport, device, queue,
+            )),
+            Backend::Cpu => Box::new(CpuPipeline::from_snapshot(
+                snapshot, format, viewport, device, queue,
+            )),
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    fmt, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use half::f16;
+
+use crate::{
+    agents,
+    settings::{AppSettings, MAX_AGENTS},
+    shader_pipeline::{self, Agent},
+};
+
+const MAGIC: &[u8; 8] = b"SLIMESNP";
+
+/// Bumped whenever the layout below changes. Older snapshots are rejected
+/// rather than misread.
+const VERSION: u32 = 2;
+
+/// Settings JSON is a few kilobytes; anything past this is a corrupt file.
+const MAX_SETTINGS_LEN: u64 = 1 << 20;
+
+/// Everything needed to carry on a run exactly where it was saved.
+///
+/// On disk this is the magic bytes and version, followed by a zlib stream
+/// holding the settings as JSON, the seed, the step counter, the agents and
+/// the raw trail map and obstacle mask, all little-endian.
+pub struct Snapshot {
+    pub settings: AppSettings,
+    pub seed: u64,
+    pub step: u32,
+    /// The slots in use, dead agents included. `settings.num_agents` counts
+    /// them all.
+    pub agents: Vec<Agent>,
+    /// Trail map texels, row by row from row zero of the simulation.
+    pub trail_map: Vec<[f16; 4]>,
+    /// One byte per texel in the same order, non-zero on walls.
+    pub obstacles: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let io_error = |err| SnapshotError::Io {
+            path: path.to_owned(),
+            source: err,
+        };
+
+        let settings = serde_json::to_vec(&self.settings)
+            .map_err(|err| SnapshotError::Invalid(err.to_string()))?;
+
+        let mut file = io::BufWriter::new(fs::File::create(path).map_err(io_error)?);
+        file.write_all(MAGIC).map_err(io_error)?;
+        file.write_all(&VERSION.to_le_bytes()).map_err(io_error)?;
+
+        let mut encoder = ZlibEncoder::new(file, Compression::fast());
+
+        let sections: [&[u8]; 7] = [
+            &(settings.len() as u64).to_le_bytes(),
+            &settings,
+            &self.seed.to_le_bytes(),
+            &self.step.to_le_bytes(),
+            bytemuck::cast_slice(&self.agents),
+            bytemuck::cast_slice(&self.trail_map),
+            &self.obstacles,
+        ];
+
+        for section in sections {
+            encoder.write_all(section).map_err(io_error)?;
+        }
+
+        encoder
+            .finish()
+            .and_then(|mut file| file.flush())
+            .map_err(io_error)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SnapshotError> {
+        let io_error = |err| SnapshotError::Io {
+            path: path.to_owned(),
+            source: err,
+        };
+
+        let mut file = io::BufReader::new(fs::File::open(path).map_err(io_error)?);
+
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic).map_err(io_error)?;
+
+        if &magic != MAGIC {
+            return Err(SnapshotError::NotASnapshot(path.to_owned()));
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut file).map_err(io_error)?);
+
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut decoder = ZlibDecoder::new(file);
+
+        let settings_len = u64::from_le_bytes(read_array(&mut decoder).map_err(io_error)?);
+
+        if settings_len > MAX_SETTINGS_LEN {
+            return Err(SnapshotError::Invalid(format!(
+                "settings take {} bytes, more than the {} allowed",
+                settings_len, MAX_SETTINGS_LEN
+            )));
+        }
+
+        let settings = read_vec::<u8>(&mut decoder, settings_len as usize).map_err(io_error)?;
+        let settings: AppSettings = serde_json::from_slice(&settings)
+            .map_err(|err| SnapshotError::Invalid(err.to_string()))?;
+
+        // The init and obstacle images only matter when spawning, so a
+        // snapshot stays loadable after they have been moved or deleted.
+        AppSettings {
+            init_image: None,
+            obstacles: None,
+            ..settings.clone()
+        }
+        .validate()
+        .map_err(|err| SnapshotError::Invalid(err.to_string()))?;
+
+        // `validate` checks this too; it is repeated here as the count sizes
+        // the read below.
+        if settings.num_agents > MAX_AGENTS {
+            return Err(SnapshotError::Invalid(format!(
+                "{} agents, more than the {} allowed",
+                settings.num_agents, MAX_AGENTS
+            )));
+        }
+
+        let seed = u64::from_le_bytes(read_array(&mut decoder).map_err(io_error)?);
+        let step = u32::from_le_bytes(read_array(&mut decoder).map_err(io_error)?);
+
+        let agents = read_vec(&mut decoder, settings.num_agents).map_err(io_error)?;
+        check_agents(&agents, &settings)?;
+
+        let texel_count = settings.width as usize * settings.height as usize;
+        let trail_map = read_vec(&mut decoder, texel_count).map_err(io_error)?;
+        let obstacles = read_vec(&mut decoder, texel_count).map_err(io_error)?;
+
+        // Reading on to the end of the stream checks it is whole.
+        if decoder.read(&mut [0]).map_err(io_error)? != 0 {
+            return Err(SnapshotError::Invalid(
+                "unexpected data after the obstacle mask".into(),
+            ));
+        }
+
+        Ok(Self {
+            settings,
+            seed,
+            step,
+            agents,
+            trail_map,
+            obstacles,
+        })
+    }
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Reads `len` values. Memory grows with the data actually read, so a
+/// corrupt length fails at the end of the stream instead of allocating it.
+fn read_vec<T: bytemuck::Pod>(reader: &mut impl Read, len: usize) -> io::Result<Vec<T>> {
+    let byte_len = len
+        .checked_mul(std::mem::size_of::<T>())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "section too large"))?;
+
+    let mut bytes = Vec::new();
+    reader.take(byte_len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != byte_len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(shader_pipeline::read_unaligned(&bytes))
+}
+
+/// Rejects agents of a species the settings do not have, or outside the
+/// map, which would index out of bounds in either backend. Agents deleted
+/// by the brush are kept as they are.
+fn check_agents(agents: &[Agent], settings: &AppSettings) -> Result<(), SnapshotError> {
+    let size = [settings.width as f32, settings.height as f32];
+
+    for (index, agent) in agents.iter().enumerate() {
+        if !agents::is_alive(agent) {
+            continue;
+        }
+
+        if agent.species_index as usize >= settings.species.len() {
+            return Err(SnapshotError::Invalid(format!(
+                "agent {} has species {}, but there are only {}",
+                index,
+                agent.species_index,
+                settings.species.len()
+            )));
+        }
+
+        let on_map = agent
+            .position
+            .iter()
+            .zip(size)
+            .all(|(&position, size)| (0.0..size).contains(&position));
+
+        if !on_map || !agent.angle.is_finite() {
+            return Err(SnapshotError::Invalid(format!(
+                "agent {} is not on the map",
+                index
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io { path: PathBuf, source: io::Error },
+    NotASnapshot(PathBuf),
+    UnsupportedVersion(u32),
+    Invalid(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io { path, source } => {
+                write!(
+                    f,
+                    "could not access snapshot {}: {}",
+                    path.display(),
+                    source
+                )
+            }
+            SnapshotError::NotASnapshot(path) => {
+                write!(f, "{} is not a slime snapshot", path.display())
+            }
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "snapshot version {} is not supported (expected {})",
+                version, VERSION
+            ),
+            SnapshotError::Invalid(reason) => write!(f, "invalid snapshot: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::tests::temp_path;
+
+    fn small_snapshot() -> Snapshot {
+        let settings = AppSettings {
+            width: 4,
+            height: 3,
+            num_agents: 3,
+            seed: Some(9),
+            ..AppSettings::default()
+        };
+
+        Snapshot {
+            seed: 9,
+            step: 17,
+            agents: vec![
+                Agent {
+                    position: [0.5, 1.5],
+                    angle: 1.0,
+                    species_index: 0,
+                },
+                Agent {
+                    position: [3.0, 2.0],
+                    angle: -2.0,
+                    species_index: 0,
+                },
+                Agent {
+                    position: [0.0, 0.0],
+                    angle: 0.0,
+                    species_index: agents::DEAD,
+                },
+            ],
+            trail_map: (0..12)
+                .map(|texel| [f16::from_f32(texel as f32 / 12.0); 4])
+                .collect(),
+            obstacles: (0..12).map(|texel| (texel % 5 == 0) as u8).collect(),
+            settings,
+        }
+    }
+
+    fn assert_same(a: &Snapshot, b: &Snapshot) {
+        assert_eq!(a.settings, b.settings);
+        assert_eq!(a.seed, b.seed);
+        assert_eq!(a.step, b.step);
+        assert_eq!(
+            bytemuck::cast_slice::<Agent, u8>(&a.agents),
+            bytemuck::cast_slice::<Agent, u8>(&b.agents)
+        );
+        assert_eq!(
+            bytemuck::cast_slice::<[f16; 4], u16>(&a.trail_map),
+            bytemuck::cast_slice::<[f16; 4], u16>(&b.trail_map)
+        );
+        assert_eq!(a.obstacles, b.obstacles);
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = temp_path("round-trip.snap");
+        let snapshot = small_snapshot();
+
+        snapshot.save(&path).unwrap();
+
+        assert_same(&Snapshot::load(&path).unwrap(), &snapshot);
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        let path = temp_path("not-a.snap");
+        fs::write(&path, b"PNG and then some").unwrap();
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::NotASnapshot(_))
+        ));
+
+        let path = temp_path("old.snap");
+        small_snapshot().save(&path).unwrap();
+
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&1u32.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::UnsupportedVersion(1))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let path = temp_path("truncated.snap");
+        small_snapshot().save(&path).unwrap();
+
+        let bytes = fs::read(&path).unwrap();
+
+        for len in [4, MAGIC.len() + 2, bytes.len() / 2, bytes.len() - 1] {
+            fs::write(&path, &bytes[..len]).unwrap();
+
+            assert!(Snapshot::load(&path).is_err(), "loaded {} bytes", len);
+        }
+    }
+
+    /// Writes the header and `body` compressed as `save` does.
+    fn write_raw(path: &Path, body: &[u8]) {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(body).unwrap();
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(encoder.finish().unwrap());
+
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn rejects_oversized_settings() {
+        let path = temp_path("huge-settings.snap");
+        write_raw(&path, &u64::MAX.to_le_bytes());
+
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_too_many_agents() {
+        let path = temp_path("many-agents.snap");
+        let mut snapshot = small_snapshot();
+        snapshot.settings.num_agents = MAX_AGENTS + 1;
+        snapshot.save(&path).unwrap();
+
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_species_and_stray_agents() {
+        let path = temp_path("bad-agents.snap");
+
+        let mut snapshot = small_snapshot();
+        snapshot.agents[1].species_index = 1;
+        snapshot.save(&path).unwrap();
+
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::Invalid(_))
+        ));
+
+        let mut snapshot = small_snapshot();
+        snapshot.agents[0].position = [4.0, 0.0];
+        snapshot.save(&path).unwrap();
+
+        assert!(matches!(
+            Snapshot::load(&path),
+            Err(SnapshotError::Invalid(_))
+        ));
+    }
+}
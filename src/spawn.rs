@@ -0,0 +1,387 @@
+use std::{f32::consts::PI, path::Path};
+
+use half::f16;
+use image::imageops::FilterType;
+use rand::{
+    distributions::{Uniform, WeightedIndex},
+    prelude::Distribution,
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    cpu, obstacles,
+    pipeline::SpawnBuffer,
+    settings::{AppSettings, Facing, SpawnHeading, SpawnMode},
+    shader_pipeline::Agent,
+    snapshot::Snapshot,
+};
+
+/// A new run before its agents are placed: the trail map, empty or seeded
+/// from the init image, the walls from the obstacle image and the seed.
+pub struct Start {
+    /// Holds no agents yet.
+    pub snapshot: Snapshot,
+    /// The init image, when the agents are drawn from it.
+    image: Option<ImageSource>,
+}
+
+impl Start {
+    pub fn new(settings: AppSettings) -> Self {
+        let size = PhysicalSize::new(settings.width, settings.height);
+
+        let image = settings.init_image.as_ref().and_then(|init_image| {
+            ImageSource::load(&init_image.path, &size)
+                .map_err(|err| {
+                    log::error!(
+                        "Could not load init image {}: {}",
+                        init_image.path.display(),
+                        err
+                    )
+                })
+                .ok()
+        });
+
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        log::info!("Simulation seed {}", seed);
+
+        let trail_map = match settings.init_image.as_ref().zip(image.as_ref()) {
+            Some((init_image, image)) if init_image.seed_trails => {
+                image.trail_texels(&settings, init_image.per_species)
+            }
+            _ => vec![[f16::ZERO; 4]; size.width as usize * size.height as usize],
+        };
+
+        let obstacles = settings
+            .obstacles
+            .as_ref()
+            .and_then(|obstacles| {
+                ImageSource::load(&obstacles.path, &size)
+                    .map(|image| image.obstacle_mask(obstacles.invert))
+                    .map_err(|err| {
+                        log::error!(
+                            "Could not load obstacle image {}: {}",
+                            obstacles.path.display(),
+                            err
+                        )
+                    })
+                    .ok()
+            })
+            .unwrap_or_else(|| vec![0; size.width as usize * size.height as usize]);
+
+        let spawns_from_image = settings
+            .init_image
+            .as_ref()
+            .is_some_and(|init_image| init_image.spawn_agents);
+
+        Self {
+            snapshot: Snapshot {
+                settings,
+                seed,
+                step: 0,
+                agents: Vec::new(),
+                trail_map,
+                obstacles,
+            },
+            image: image.filter(|_| spawns_from_image),
+        }
+    }
+
+    /// Whether the agents follow the spawn pattern, which `SpawnPipeline`
+    /// can place where they are stored.
+    pub fn follows_pattern(&self) -> bool {
+        self.image.is_none()
+    }
+
+    /// The agents of the spawn pattern.
+    pub fn pattern(&self) -> SpawnBuffer {
+        let snapshot = &self.snapshot;
+
+        SpawnBuffer::new(
+            &snapshot.settings,
+            snapshot.seed,
+            snapshot.step,
+            0,
+            snapshot.settings.num_agents,
+        )
+    }
+
+    /// Places the agents on the CPU.
+    pub fn into_snapshot(self) -> Snapshot {
+        let agents = match &self.image {
+            Some(image) => image_agents(
+                &self.snapshot.settings,
+                image,
+                &mut StdRng::seed_from_u64(self.snapshot.seed),
+            ),
+            None => cpu::spawned_agents(&self.pattern()),
+        };
+
+        Snapshot {
+            agents,
+            ..self.snapshot
+        }
+    }
+}
+
+/// The state a new run starts from, with its agents placed on the CPU.
+pub fn initial_snapshot(settings: AppSettings) -> Snapshot {
+    Start::new(settings).into_snapshot()
+}
+
+/// Creates `settings.num_agents` agents drawn from the brightness of
+/// `image`, with species assigned round-robin. Species the image has no
+/// brightness for follow the spawn pattern instead.
+fn image_agents<R: Rng>(settings: &AppSettings, image: &ImageSource, rng: &mut R) -> Vec<Agent> {
+    let size = PhysicalSize::new(settings.width, settings.height);
+    let num_species = settings.species.len();
+    let per_species = settings
+        .init_image
+        .as_ref()
+        .is_some_and(|init_image| init_image.per_species);
+
+    // One distribution per species, or `None` where the image is empty.
+    let distributions = (0..num_species)
+        .map(|species_index| {
+            let distribution = image.distribution(per_species.then_some(species_index));
+
+            if distribution.is_none() {
+                log::warn!(
+                    "Init image has no brightness for species {}, using the spawn pattern",
+                    species_index + 1
+                );
+            }
+
+            distribution
+        })
+        .collect::<Vec<_>>();
+
+    (0..settings.num_agents)
+        .map(|index| {
+            let species_index = index % num_species;
+
+            let (position, angle) = match &distributions[species_index] {
+                Some(distribution) => image_position(distribution, &size, rng),
+                None => spawn_position(&settings.spawn, &size, rng),
+            };
+
+            Agent {
+                position,
+                angle,
+                species_index: species_index as u32,
+            }
+        })
+        .collect()
+}
+
+/// An init image decoded and resampled to the simulation size.
+pub struct ImageSource {
+    pixels: Vec<[f32; 4]>,
+}
+
+impl ImageSource {
+    pub fn load(path: &Path, size: &PhysicalSize<u32>) -> Result<Self, image::ImageError> {
+        // Row zero of the simulation is drawn at the bottom of the window,
+        // so the image is flipped to appear upright.
+        let image = image::open(path)?
+            .resize_exact(size.width, size.height, FilterType::Triangle)
+            .flipv()
+            .to_rgba32f();
+
+        let pixels = image.pixels().map(|pixel| pixel.0).collect();
+
+        Ok(Self { pixels })
+    }
+
+    /// Trail map contents seeded from the image. Each species channel gets
+    /// either the matching image channel or the image's luminance.
+    pub fn trail_texels(&self, settings: &AppSettings, per_species: bool) -> Vec<[f16; 4]> {
+        let num_species = settings.species.len();
+
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                let mut texel = [f16::ZERO; 4];
+
+                for (channel, value) in texel.iter_mut().enumerate().take(num_species) {
+                    let intensity = if per_species {
+                        pixel[channel]
+                    } else {
+                        luminance(pixel)
+                    };
+
+                    *value = f16::from_f32(intensity.clamp(0.0, 1.0));
+                }
+
+                texel
+            })
+            .collect()
+    }
+
+    /// Brightness of every pixel, row by row.
+    pub fn luminance(&self) -> impl Iterator<Item = f32> + '_ {
+        self.pixels.iter().map(luminance)
+    }
+
+    /// Walls where the image is brighter than half, or darker with
+    /// `invert`.
+    pub fn obstacle_mask(&self, invert: bool) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .map(|pixel| {
+                let wall = (luminance(pixel) > 0.5) != invert;
+
+                if wall {
+                    obstacles::WALL
+                } else {
+                    0
+                }
+            })
+            .collect()
+    }
+
+    fn distribution(&self, channel: Option<usize>) -> Option<WeightedIndex<f32>> {
+        let weights = self.pixels.iter().map(|pixel| match channel {
+            Some(channel) => pixel[channel].max(0.0),
+            None => luminance(pixel),
+        });
+
+        WeightedIndex::new(weights).ok()
+    }
+}
+
+fn luminance(pixel: &[f32; 4]) -> f32 {
+    let [r, g, b, a] = *pixel;
+
+    ((0.2126 * r + 0.7152 * g + 0.0722 * b) * a).max(0.0)
+}
+
+/// Picks a random pixel weighted by the image and a random spot inside it.
+fn image_position<R: Rng>(
+    distribution: &WeightedIndex<f32>,
+    size: &PhysicalSize<u32>,
+    rng: &mut R,
+) -> ([f32; 2], f32) {
+    let unit = Uniform::new(0.0f32, 1.0);
+    let pixel = distribution.sample(rng);
+
+    let x = (pixel % size.width as usize) as f32 + unit.sample(rng);
+    let y = (pixel / size.width as usize) as f32 + unit.sample(rng);
+
+    let position = [
+        x.min(size.width as f32 - 1.0),
+        y.min(size.height as f32 - 1.0),
+    ];
+
+    (position, unit.sample(rng) * PI * 2.0)
+}
+
+/// Picks a position and heading for a single agent.
+fn spawn_position<R: Rng>(
+    spawn: &SpawnMode,
+    size: &PhysicalSize<u32>,
+    rng: &mut R,
+) -> ([f32; 2], f32) {
+    let unit = Uniform::new(0.0f32, 1.0);
+    let width = size.width as f32;
+    let height = size.height as f32;
+    let center = [width / 2.0, height / 2.0];
+
+    let random_angle = unit.sample(rng) * PI * 2.0;
+
+    let (position, angle) = match *spawn {
+        SpawnMode::Point => (center, random_angle),
+        SpawnMode::Random => (
+            [unit.sample(rng) * width, unit.sample(rng) * height],
+            random_angle,
+        ),
+        SpawnMode::Circle { radius } => {
+            // sqrt keeps the density uniform over the disc.
+            let distance = radius * unit.sample(rng).sqrt();
+            let theta = unit.sample(rng) * PI * 2.0;
+
+            (
+                [
+                    center[0] + distance * theta.cos(),
+                    center[1] + distance * theta.sin(),
+                ],
+                theta + PI,
+            )
+        }
+        SpawnMode::Ring { radius, facing } => {
+            let theta = unit.sample(rng) * PI * 2.0;
+            let angle = match facing {
+                Facing::Inward => theta + PI,
+                Facing::Outward => theta,
+            };
+
+            (
+                [
+                    center[0] + radius * theta.cos(),
+                    center[1] + radius * theta.sin(),
+                ],
+                angle,
+            )
+        }
+        SpawnMode::Rect {
+            width: rect_width,
+            height: rect_height,
+        } => (
+            [
+                center[0] + (unit.sample(rng) - 0.5) * rect_width,
+                center[1] + (unit.sample(rng) - 0.5) * rect_height,
+            ],
+            random_angle,
+        ),
+    };
+
+    let position = [
+        position[0].clamp(0.0, width - 1.0),
+        position[1].clamp(0.0, height - 1.0),
+    ];
+
+    (position, angle)
+}
+
+/// A burst of `count` agents from the spawn brush, spread over its disc
+/// around `center`. Species continue round-robin from `first_index`, the
+/// slot the first of them goes in.
+pub fn brush_agents<R: Rng>(
+    settings: &AppSettings,
+    center: [f32; 2],
+    first_index: usize,
+    count: usize,
+    rng: &mut R,
+) -> Vec<Agent> {
+    let unit = Uniform::new(0.0f32, 1.0);
+    let num_species = settings.species.len();
+    let max_position = [settings.width as f32 - 1.0, settings.height as f32 - 1.0];
+
+    (first_index..first_index + count)
+        .map(|index| {
+            let distance = settings.brush.radius * unit.sample(rng).sqrt();
+            let theta = unit.sample(rng) * PI * 2.0;
+
+            let angle = match settings.brush.spawn_heading {
+                SpawnHeading::Random => unit.sample(rng) * PI * 2.0,
+                SpawnHeading::Outward => theta,
+            };
+
+            Agent {
+                position: [
+                    (center[0] + distance * theta.cos()).clamp(0.0, max_position[0]),
+                    (center[1] + distance * theta.sin()).clamp(0.0, max_position[1]),
+                ],
+                angle,
+                species_index: (index % num_species) as u32,
+            }
+        })
+        .collect()
+}
+
+/// The RNG behind the spawn brush's burst at `step`, so both backends spawn
+/// the same agents.
+pub fn brush_rng(seed: u64, step: u32) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (step as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}